@@ -1,16 +1,56 @@
-use egui::{plot::Plot, Ui};
+use crate::coords::{self, GeoBoundingBox, GeoCoord, METERS_PER_DEGREE};
+use crate::style::{auto_color_for_tag, StyleSheet};
 use crate::zana;
-use crate::zana::Path;
+use crate::zana::{LoadOptions, MapData, Path, Poi, ZanaError};
+use egui::plot::{Plot, PlotBounds, PlotPoint, PlotTransform, PlotUi, Points};
+use egui::{epaint::RectShape, Color32, Mesh, Rounding, Shape, Ui};
+use osmpbfreader::Tags;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
-
 pub struct TemplateApp {
     nodes: Vec<Path>,
+    pois: Vec<Poi>,
+    load_error: Option<ZanaError>,
+    /// Plot coordinates are decimicro degrees relative to this point
+    origin: (i32, i32),
+    view: ViewState,
+    /// Tags of the feature nearest to the last click, and how far it was in meters
+    selected: Option<(Tags, f64)>,
+    options: MapOptions,
+    stats: RenderStats,
+}
+
+/// What is moving the view besides the plot's own dragging.
+#[derive(Default)]
+struct ViewState {
+    flight: Option<Flight>,
+    touches: Touches,
+    /// Whether the view has been placed on the data. Until then egui fits the view to what is
+    /// drawn, and with culling that would be next to nothing.
+    placed: bool,
+    #[cfg(target_arch = "wasm32")]
+    permalink: crate::permalink::Permalink,
+}
+
+/// What to draw and how.
+struct MapOptions {
+    styles: StyleSheet,
+    visible_groups: HashSet<FeatureGroup>,
+    /// Ignore the styling and give every tag key its own colour, handy for exploring new data.
+    color_by_tag: bool,
 }
 
 impl TemplateApp {
     /// Called once before the first frame.
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        Self::with_styles(cc, StyleSheet::default())
+    }
+
+    /// Like [`TemplateApp::new`], with custom tag styling. The extract is loaded with it,
+    /// so ways that only a custom rule covers, say `railway`, are on the map too.
+    pub fn with_styles(_cc: &eframe::CreationContext<'_>, styles: StyleSheet) -> Self {
         // This is also where you can customize the look and feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
 
@@ -21,28 +61,278 @@ impl TemplateApp {
         // }
 
         // Default::default()
-        Self {
-            nodes: zana::read_nodes_from_file(),
+        let (data, load_error) = match zana::read_nodes_from_file(&LoadOptions {
+            remove_collinear: true,
+            styles: styles.clone(),
+            ..Default::default()
+        }) {
+            Ok(data) => (data, None),
+            Err(e) => {
+                log::error!("Failed to load map data: {e}");
+                let empty = MapData {
+                    paths: vec![],
+                    pois: vec![],
+                };
+                (empty, Some(e))
+            }
+        };
+        let nodes = data.paths;
+        let origin = nodes.first().map(|p| p.points[0]).unwrap_or_default();
+        // A link with a `#map=` fragment opens on the view it was shared from
+        #[cfg(target_arch = "wasm32")]
+        let shared_view = crate::permalink::parse(&_cc.integration_info.web_info.location.hash);
+        #[cfg(not(target_arch = "wasm32"))]
+        let shared_view: Option<(f64, GeoCoord)> = None;
+        let mut app = Self {
+            nodes,
+            pois: data.pois,
+            load_error,
+            origin,
+            view: ViewState::default(),
+            selected: None,
+            options: MapOptions {
+                styles,
+                visible_groups: FeatureGroup::ALL.into_iter().collect(),
+                color_by_tag: false,
+            },
+            stats: RenderStats::default(),
+        };
+        if let Some((zoom, center)) = shared_view {
+            app.fly_to(center.lat, center.lon, zoom, None);
         }
+        app
     }
+
+    /// Centers the map on `lat`/`lon` at a slippy map zoom level, where a screen point spans
+    /// `360 / (256 * 2^zoom)` degrees as on openstreetmap.org.
+    /// With a `duration` the view glides there instead of jumping.
+    pub fn fly_to(&mut self, lat: f64, lon: f64, zoom: f64, duration: Option<Duration>) {
+        self.view.flight = Some(Flight {
+            center: plot_point(self.origin, GeoCoord::from_latlon(lat, lon)),
+            zoom,
+            duration: duration.unwrap_or_default().as_secs_f64(),
+            start: None,
+        });
+    }
+}
+
+/// Kinds of features that can be switched on and off together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum FeatureGroup {
+    Roads,
+    Footpaths,
+    Water,
+    Pois,
+}
+
+impl FeatureGroup {
+    const ALL: [FeatureGroup; 4] = [Self::Roads, Self::Footpaths, Self::Water, Self::Pois];
+
+    fn of(path: &Path) -> Self {
+        const FOOTPATHS: &[&str] = &[
+            "footway",
+            "path",
+            "cycleway",
+            "pedestrian",
+            "steps",
+            "bridleway",
+        ];
+        if path.is_water() || path.is_coastline() {
+            Self::Water
+        } else if FOOTPATHS.contains(&path.tag_value("highway").unwrap_or_default()) {
+            Self::Footpaths
+        } else {
+            Self::Roads
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Roads => "Roads",
+            Self::Footpaths => "Footpaths and cycleways",
+            Self::Water => "Water",
+            Self::Pois => "Points of interest",
+        }
+    }
+}
+
+/// Numbers for the debug overlay, toggled with F3.
+#[derive(Default)]
+struct RenderStats {
+    shown: bool,
+    paths: usize,
+    points: usize,
+    pois: usize,
+    /// Smoothed seconds spent in our UI code per frame, as measured by eframe
+    frame_time: f32,
+}
+
+impl RenderStats {
+    fn show(&self, ctx: &egui::Context, total_paths: usize, total_pois: usize) {
+        egui::Area::new("render_stats")
+            .anchor(egui::Align2::RIGHT_TOP, [-10.0, 10.0])
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(format!("paths: {} of {total_paths}", self.paths));
+                    ui.label(format!("points: {}", self.points));
+                    ui.label(format!("POIs: {} of {total_pois}", self.pois));
+                    ui.label(format!("frame: {:.1} ms", self.frame_time * 1000.0));
+                });
+            });
+    }
+}
+
+/// Fingers currently on the screen, in screen points.
+/// egui only reports how far a pinch zoomed, not where, so we follow the touches ourselves.
+#[derive(Default)]
+struct Touches(BTreeMap<egui::TouchId, egui::Pos2>);
+
+impl Touches {
+    fn update(&mut self, ctx: &egui::Context) {
+        ctx.input(|i| {
+            for event in &i.events {
+                if let egui::Event::Touch { id, phase, pos, .. } = event {
+                    match phase {
+                        egui::TouchPhase::Start | egui::TouchPhase::Move => {
+                            self.0.insert(*id, *pos);
+                        }
+                        egui::TouchPhase::End | egui::TouchPhase::Cancel => {
+                            self.0.remove(id);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Midpoint of the fingers during a pinch, `None` with fewer than two.
+    fn pinch_center(&self) -> Option<egui::Pos2> {
+        if self.0.len() < 2 {
+            return None;
+        }
+        let sum = self
+            .0
+            .values()
+            .fold(egui::Vec2::ZERO, |sum, p| sum + p.to_vec2());
+        Some((sum / self.0.len() as f32).to_pos2())
+    }
+}
+
+/// An in-progress [`TemplateApp::fly_to`].
+struct Flight {
+    center: [f64; 2],
+    zoom: f64,
+    duration: f64,
+    /// Start time and view, known once the plot is shown
+    start: Option<(f64, PlotBounds)>,
+}
+
+impl Flight {
+    /// Where the flight ends, given the size of the plot.
+    fn target(&self, frame: &egui::Rect) -> PlotBounds {
+        let (width, height) = (
+            frame.width().max(1.0) as f64,
+            frame.height().max(1.0) as f64,
+        );
+        // `#map=2000/60/25` would otherwise ask for a view zero units wide
+        let per_point =
+            units_per_point(self.zoom).clamp(MIN_VIEW_WIDTH / width, MAX_VIEW_WIDTH / width);
+        let [x, y] = self.center;
+        let (dx, dy) = (width * per_point / 2.0, height * per_point / 2.0);
+        PlotBounds::from_min_max([x - dx, y - dy], [x + dx, y + dy])
+    }
+
+    /// The view at `now`, and whether the flight is over.
+    fn bounds_at(
+        &mut self,
+        now: f64,
+        current: PlotBounds,
+        frame: &egui::Rect,
+    ) -> (PlotBounds, bool) {
+        let target = self.target(frame);
+        let (start_time, from) = *self.start.get_or_insert((now, current));
+        let t = if self.duration > 0.0 {
+            ((now - start_time) / self.duration).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let eased = t * t * (3.0 - 2.0 * t);
+        let lerp =
+            |a: [f64; 2], b: [f64; 2]| [a[0] + (b[0] - a[0]) * eased, a[1] + (b[1] - a[1]) * eased];
+        let bounds = PlotBounds::from_min_max(
+            lerp(from.min(), target.min()),
+            lerp(from.max(), target.max()),
+        );
+        (bounds, t >= 1.0)
+    }
+}
+
+/// Plot units one screen point spans at a slippy map zoom level.
+fn units_per_point(zoom: f64) -> f64 {
+    360e7 / (256.0 * 2f64.powf(zoom))
+}
+
+/// Inverse of [`units_per_point`].
+#[cfg(target_arch = "wasm32")]
+fn zoom_for(units_per_point: f64) -> f64 {
+    (360e7 / (256.0 * units_per_point)).log2()
+}
+
+fn plot_point(origin: (i32, i32), coord: GeoCoord) -> [f64; 2] {
+    // Paths store negated coordinates, see `zana::read_nodes_from_file`
+    [
+        origin.0 as f64 + coord.lon * 1e7,
+        origin.1 as f64 + coord.lat * 1e7,
+    ]
+}
+
+fn stored_to_plot(origin: (i32, i32), point: (i32, i32)) -> [f64; 2] {
+    [(origin.0 - point.0) as f64, (origin.1 - point.1) as f64]
+}
+
+/// Inverse of [`plot_point`].
+fn plot_to_geo(origin: (i32, i32), point: [f64; 2]) -> GeoCoord {
+    GeoCoord::from_latlon(
+        (point[1] - origin.1 as f64) * 1e-7,
+        (point[0] - origin.0 as f64) * 1e-7,
+    )
 }
 
 impl eframe::App for TemplateApp {
     /// Called by the frame work to save state before shutdown.
-    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+    fn save(&mut self, _storage: &mut dyn eframe::Storage) {
         // eframe::set_value(storage, eframe::APP_KEY, self);
     }
 
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let Self { nodes } = self;
+        let Self {
+            nodes,
+            pois,
+            load_error,
+            origin,
+            view,
+            selected,
+            options,
+            stats,
+        } = self;
+        view.touches.update(ctx);
+
+        if ctx.input(|i| i.key_pressed(egui::Key::F3)) {
+            stats.shown = !stats.shown;
+        }
+        if let Some(cpu) = _frame.info().cpu_usage {
+            stats.frame_time = egui::lerp(stats.frame_time..=cpu, 0.1);
+        }
 
         // Examples of how to create different panels and windows.
         // Pick whichever suits you.
         // Tip: a good default choice is to just keep the `CentralPanel`.
         // For inspiration and more examples, go to https://emilk.github.io/egui
 
+        let mut fit_to_data = false;
+
         #[cfg(not(target_arch = "wasm32"))] // no File->Quit on web pages!
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
@@ -57,6 +347,25 @@ impl eframe::App for TemplateApp {
 
         egui::SidePanel::left("side_panel").show(ctx, |ui| {
             ui.heading("Map options");
+            for group in FeatureGroup::ALL {
+                let mut visible = options.visible_groups.contains(&group);
+                if ui.checkbox(&mut visible, group.label()).changed() {
+                    if visible {
+                        options.visible_groups.insert(group);
+                    } else {
+                        options.visible_groups.remove(&group);
+                    }
+                }
+            }
+            ui.checkbox(&mut options.color_by_tag, "Color by tag key");
+            fit_to_data = ui.button("Fit to data").clicked();
+            if let Some((tags, distance)) = selected {
+                ui.separator();
+                ui.label(format!("Nearest feature, {distance:.0} m away:"));
+                for (k, v) in tags.iter() {
+                    ui.label(format!("{k} = {v}"));
+                }
+            }
             ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                 ui.horizontal(|ui| {
                     ui.spacing_mut().item_spacing.x = 0.0;
@@ -71,16 +380,115 @@ impl eframe::App for TemplateApp {
             });
         });
 
+        // What the panels left over is the map
+        let map_size = ctx.available_rect().size().max(egui::vec2(1.0, 1.0));
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
-            draw_line(nodes, ui);
+            if let Some(e) = load_error {
+                ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+            }
+            (stats.paths, stats.points, stats.pois) =
+                draw_line(nodes, pois, *origin, view, selected, options, ui);
             egui::warn_if_debug_build(ui);
         });
+
+        if stats.shown {
+            stats.show(ctx, nodes.len(), self.pois.len());
+        }
+
+        if fit_to_data {
+            if let Some((center, radius)) = zana::bounding_circle(&self.nodes) {
+                let (lat, lon) = center.to_latlon();
+                // Plot x is longitude, which is what gets squeezed away from the equator,
+                // while a degree of latitude keeps its length. The view is as many degrees tall
+                // as it is wide per point, so a wide window can run out of height first.
+                let diameter = 2.0 * radius.max(MIN_FIT_RADIUS_M);
+                let fit_width = diameter / coords::meters_per_point(lat, 1.0);
+                let fit_height = diameter / METERS_PER_DEGREE * (map_size.x / map_size.y) as f64;
+                let width = fit_width.max(fit_height);
+                let zoom = (360.0 / (256.0 * width / map_size.x as f64)).log2();
+                self.fly_to(lat, lon, zoom, Some(Duration::from_millis(500)));
+                ctx.request_repaint();
+            }
+        }
     }
 }
 
-fn draw_line(nodes: &[Path], ui: &mut Ui) {
-    use egui::plot::{Line, PlotPoints};
+/// Keeps "Fit to data" from zooming into a single point forever
+const MIN_FIT_RADIUS_M: f64 = 50.0;
+/// Zoom factor for one mouse wheel notch, which egui reports as 50 points of scrolling
+const WHEEL_ZOOM_PER_NOTCH: f64 = 1.1;
+const POINTS_PER_NOTCH: f64 = 50.0;
+/// Narrowest and widest view in plot units, about a meter and the whole world
+const MIN_VIEW_WIDTH: f64 = 100.0;
+const MAX_VIEW_WIDTH: f64 = 360e7;
+/// How close to a POI a click has to be to select it, in screen points
+const POI_PICK_RADIUS_POINTS: f64 = 8.0;
+/// How far a simplified line may stray from the real one, in screen points
+const SIMPLIFY_TOLERANCE_POINTS: f64 = 0.5;
+/// How far an arrow key press moves the view, in screen points
+const KEY_PAN_STEP: f64 = 50.0;
+const KEY_ZOOM_FACTOR: f64 = 1.25;
+
+/// Scales `bounds` by `factor` around `center`, which stays put on screen.
+/// Factors above one zoom in. The resulting width is kept within the view limits.
+fn zoom_around(bounds: PlotBounds, center: [f64; 2], factor: f64) -> PlotBounds {
+    let width = bounds.width() / factor;
+    let factor = bounds.width() / width.clamp(MIN_VIEW_WIDTH, MAX_VIEW_WIDTH);
+    let scale = |p: [f64; 2]| {
+        [
+            center[0] + (p[0] - center[0]) / factor,
+            center[1] + (p[1] - center[1]) / factor,
+        ]
+    };
+    PlotBounds::from_min_max(scale(bounds.min()), scale(bounds.max()))
+}
+
+/// Arrow keys pan and +/- zoom around the middle of the view.
+/// Returns whether the view moved. Keys are left alone while a widget has keyboard focus.
+fn pan_and_zoom_with_keys(plot_ui: &mut PlotUi) -> bool {
+    use egui::Key;
+    let ctx = plot_ui.ctx().clone();
+    if ctx.wants_keyboard_input() {
+        return false;
+    }
+    let pressed = |key| ctx.input(|i| i.key_pressed(key)) as i32 as f64;
+    let steps = [
+        pressed(Key::ArrowRight) - pressed(Key::ArrowLeft),
+        pressed(Key::ArrowUp) - pressed(Key::ArrowDown),
+    ];
+    let zoom = pressed(Key::PlusEquals) - pressed(Key::Minus);
+    if steps == [0.0, 0.0] && zoom == 0.0 {
+        return false;
+    }
+    let scale = plot_ui.transform().dvalue_dpos();
+    let bounds = plot_ui.plot_bounds();
+    let shift = |p: [f64; 2]| {
+        [
+            p[0] + steps[0] * KEY_PAN_STEP * scale[0].abs(),
+            p[1] + steps[1] * KEY_PAN_STEP * scale[1].abs(),
+        ]
+    };
+    let moved = PlotBounds::from_min_max(shift(bounds.min()), shift(bounds.max()));
+    let center = moved.center();
+    plot_ui.set_plot_bounds(zoom_around(
+        moved,
+        [center.x, center.y],
+        KEY_ZOOM_FACTOR.powf(zoom),
+    ));
+    true
+}
+
+fn draw_line(
+    nodes: &[Path],
+    pois: &[Poi],
+    origin: (i32, i32),
+    view: &mut ViewState,
+    selected: &mut Option<(Tags, f64)>,
+    options: &MapOptions,
+    ui: &mut Ui,
+) -> (usize, usize, usize) {
+    use egui::plot::{Line, LineStyle, PlotPoints};
     // let n = 128;
     // let line_points: PlotPoints = (0..=n)
     //     .map(|i| {
@@ -90,24 +498,280 @@ fn draw_line(nodes: &[Path], ui: &mut Ui) {
     //     })
     //     .collect();
 
-    let beginning = nodes[0].points[0];
-
-    let lines: Vec<_> = nodes
+    let mut visible: Vec<_> = nodes
         .iter()
-        .map(|p| {
-            Line::new(PlotPoints::new(
-                p.points
-                    .iter()
-                    .map(|(x, y)| [(beginning.0 - *x) as f64, (beginning.1 - *y) as f64])
-                    .collect(),
-            ))
-        })
+        .filter(|p| options.visible_groups.contains(&FeatureGroup::of(p)))
         .collect();
+    // Lines are painted in order, so bridges come after what they cross and roads after water.
+    // The sort is stable, ties keep the order of the data.
+    visible.sort_by_cached_key(|p| (p.layer(), options.styles.resolve(&p.tags).z_index));
+    let mut drawn = vec![];
+    let mut drawn_pois = vec![];
+    // What egui is actually handed, after simplification
+    let mut points = 0;
+
+    // egui can only fill convex shapes itself, so fills are painted as a mesh under the plot.
+    // That means painting the plot background ourselves too, to keep it below the fills.
+    let background = ui.painter().add(Shape::Noop);
+    let fills = ui.painter().add(Shape::Noop);
 
     // Line::new(line_points);
-    Plot::new("example_plot")
+    let mut moved = false;
+    let response = Plot::new("example_plot")
         .data_aspect(1.0)
+        .show_background(false)
+        // The wheel zooms instead of scrolling, and all zooming is done below
+        .allow_scroll(false)
+        .allow_zoom(false)
+        // egui would go back to fitting the view to what is drawn, see `ViewState::placed`
+        .allow_double_click_reset(false)
         .show(ui, |plot_ui| {
-            lines.into_iter().for_each(|l| plot_ui.line(l))
+            // Only bounds we placed ourselves are worth culling by
+            let cull = view.placed;
+            if !view.placed && view.flight.is_none() {
+                if let Some(bounds) = data_bounds(nodes, origin, plot_ui.transform().frame()) {
+                    plot_ui.set_plot_bounds(bounds);
+                    moved = true;
+                }
+            }
+            if let Some(f) = &mut view.flight {
+                let now = plot_ui.ctx().input(|i| i.time);
+                let frame = *plot_ui.transform().frame();
+                let (bounds, landed) = f.bounds_at(now, plot_ui.plot_bounds(), &frame);
+                plot_ui.set_plot_bounds(bounds);
+                moved = true;
+                if landed {
+                    view.flight = None;
+                } else {
+                    plot_ui.ctx().request_repaint();
+                }
+            }
+            // egui turns ctrl+wheel and pinches into `zoom_delta`, plain wheel is left as a scroll
+            let (scroll, zoom) = plot_ui
+                .ctx()
+                .input(|i| (i.scroll_delta.y as f64, i.zoom_delta() as f64));
+            let factor = WHEEL_ZOOM_PER_NOTCH.powf(scroll / POINTS_PER_NOTCH) * zoom;
+            if factor != 1.0 {
+                // A single finger keeps panning through the plot's own dragging.
+                // Scrolling and pinching elsewhere, say over the side panel, is not for the map.
+                let center = match view.touches.pinch_center() {
+                    Some(pos) if plot_ui.transform().frame().contains(pos) => {
+                        Some(plot_ui.transform().value_from_position(pos))
+                    }
+                    Some(_) => None,
+                    None if plot_ui.plot_hovered() => plot_ui.pointer_coordinate(),
+                    None => None,
+                };
+                if let Some(center) = center {
+                    let bounds = plot_ui.plot_bounds();
+                    plot_ui.set_plot_bounds(zoom_around(bounds, [center.x, center.y], factor));
+                    view.flight = None;
+                    moved = true;
+                }
+            }
+            if pan_and_zoom_with_keys(plot_ui) {
+                view.flight = None;
+                moved = true;
+            }
+            // Widths follow last frame's zoom, egui only knows the new one after layout
+            let degrees_per_point = plot_ui.transform().dvalue_dpos()[1].abs() * 1e-7;
+            let meters_per_point = degrees_per_point * METERS_PER_DEGREE;
+            if plot_ui.plot_clicked() {
+                if let Some(pointer) = plot_ui.pointer_coordinate() {
+                    let target = plot_to_geo(origin, [pointer.x, pointer.y]);
+                    // Markers are small, so a POI right under the pointer wins over a nearer road
+                    let poi = options
+                        .visible_groups
+                        .contains(&FeatureGroup::Pois)
+                        .then(|| {
+                            let radius = POI_PICK_RADIUS_POINTS * meters_per_point;
+                            zana::nearest_poi(pois, &target, radius)
+                        })
+                        .flatten()
+                        .map(|(poi, distance)| (poi.tags.clone(), distance));
+                    // Hidden groups are not on screen to be clicked
+                    *selected = poi.or_else(|| {
+                        zana::nearest_path(visible.iter().copied(), &target)
+                            .map(|(path, distance)| (path.tags.clone(), distance))
+                    });
+                }
+            }
+            let view_box = Some(plot_ui.plot_bounds())
+                .filter(|bounds| cull && bounds.is_valid())
+                .map(|bounds| culling_box(origin, &bounds));
+            drawn = visible
+                .iter()
+                .copied()
+                .filter(|p| match (&view_box, &p.bounds) {
+                    (Some(view_box), Some(bounds)) => bounds.intersects(view_box),
+                    (None, _) => true,
+                    (_, None) => false,
+                })
+                .collect();
+            // Detail finer than this would not show, so it is not worth handing to egui
+            let tolerance_m = view_box.map_or(0.0, |b| {
+                SIMPLIFY_TOLERANCE_POINTS
+                    * coords::meters_per_point(b.center().lat, degrees_per_point)
+            });
+            for p in &drawn {
+                let simplified = zana::simplify_path(&p.points, tolerance_m);
+                points += simplified.len();
+                let line = Line::new(PlotPoints::new(
+                    simplified
+                        .into_iter()
+                        .map(|p| stored_to_plot(origin, p))
+                        .collect(),
+                ));
+                let style = options.styles.resolve(&p.tags);
+                let color = match p.feature_key() {
+                    Some(key) if options.color_by_tag => auto_color_for_tag(key),
+                    _ => style.color,
+                };
+                let line = line
+                    .color(color)
+                    .width(style.width_for_scale(meters_per_point));
+                plot_ui.line(match style.dash() {
+                    Some(length) => line.style(LineStyle::Dashed { length }),
+                    None => line,
+                });
+            }
+            if options.visible_groups.contains(&FeatureGroup::Pois) {
+                drawn_pois = pois
+                    .iter()
+                    .filter(|p| {
+                        view_box.map_or(true, |b| b.contains(&GeoCoord::from_stored(p.point)))
+                    })
+                    .collect();
+                draw_pois(
+                    plot_ui,
+                    &drawn_pois,
+                    origin,
+                    &options.styles,
+                    options.color_by_tag,
+                    meters_per_point,
+                );
+            }
         });
+
+    view.placed |= moved;
+    if response.response.double_clicked() {
+        // Back to the whole data, like egui's own reset
+        view.placed = false;
+        moved = true;
+    }
+    if moved || response.response.dragged() {
+        // Culling went by the bounds from before the move, draw again with the new ones
+        ui.ctx().request_repaint();
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let center = response.transform.bounds().center();
+        let zoom = zoom_for(response.transform.dvalue_dpos()[0]);
+        view.permalink
+            .update(ui.ctx(), zoom, plot_to_geo(origin, [center.x, center.y]));
+    }
+
+    let rect = *response.transform.frame();
+    let visuals = ui.visuals();
+    let background_shape = RectShape {
+        rect,
+        rounding: Rounding::same(2.0),
+        fill: visuals.extreme_bg_color,
+        stroke: visuals.widgets.noninteractive.bg_stroke,
+    };
+    let fill_mesh = fill_mesh(&drawn, origin, &options.styles, &response.transform);
+    ui.painter().set(background, background_shape);
+    ui.painter()
+        .with_clip_rect(rect)
+        .set(fills, Shape::mesh(fill_mesh));
+
+    (drawn.len(), points, drawn_pois.len())
+}
+
+/// A view of all of `paths` in a plot of the shape of `frame`, with egui's usual margin.
+fn data_bounds(paths: &[Path], origin: (i32, i32), frame: &egui::Rect) -> Option<PlotBounds> {
+    let extent = paths
+        .iter()
+        .filter_map(|p| p.bounds)
+        .reduce(|a, b| a.union(&b))?;
+    let [x0, y0] = plot_point(origin, extent.min);
+    let [x1, y1] = plot_point(origin, extent.max);
+    let aspect = (frame.width() / frame.height().max(1.0)) as f64;
+    let width = ((x1 - x0).max((y1 - y0) * aspect) * 1.1).clamp(MIN_VIEW_WIDTH, MAX_VIEW_WIDTH);
+    let [cx, cy] = [(x0 + x1) / 2.0, (y0 + y1) / 2.0];
+    let [half_w, half_h] = [width / 2.0, width / aspect.max(f64::EPSILON) / 2.0];
+    Some(PlotBounds::from_min_max(
+        [cx - half_w, cy - half_h],
+        [cx + half_w, cy + half_h],
+    ))
+}
+
+/// The area worth drawing for a view, in lat/lon.
+/// Bounds inside the plot are from before this frame's panning and zooming, so the box
+/// reaches a view's size past every edge to keep new edges from popping in a frame late.
+fn culling_box(origin: (i32, i32), bounds: &PlotBounds) -> GeoBoundingBox {
+    let [w, h] = [bounds.width(), bounds.height()];
+    let [x0, y0] = bounds.min();
+    let [x1, y1] = bounds.max();
+    GeoBoundingBox {
+        min: plot_to_geo(origin, [x0 - w, y0 - h]),
+        max: plot_to_geo(origin, [x1 + w, y1 + h]),
+    }
+}
+
+/// Draws a filled circle for every POI, sized by its style's width.
+fn draw_pois(
+    plot_ui: &mut PlotUi,
+    pois: &[&Poi],
+    origin: (i32, i32),
+    styles: &StyleSheet,
+    color_by_tag: bool,
+    meters_per_point: f64,
+) {
+    // One plot item per look rather than per POI, there can be hundreds of thousands of trees
+    let mut markers: HashMap<(Color32, u32), Vec<[f64; 2]>> = HashMap::new();
+    for poi in pois {
+        let style = styles.resolve(&poi.tags);
+        let radius = style.width_for_scale(meters_per_point);
+        let color = match poi.feature_key() {
+            Some(key) if color_by_tag => auto_color_for_tag(key),
+            _ => style.color,
+        };
+        markers
+            .entry((color, radius.to_bits()))
+            .or_default()
+            .push(stored_to_plot(origin, poi.point));
+    }
+    for ((color, radius), points) in markers {
+        plot_ui.points(
+            Points::new(points)
+                .color(color)
+                .radius(f32::from_bits(radius))
+                .filled(true),
+        );
+    }
+}
+
+fn fill_mesh(
+    paths: &[&Path],
+    origin: (i32, i32),
+    styles: &StyleSheet,
+    transform: &PlotTransform,
+) -> Mesh {
+    let mut mesh = Mesh::default();
+    for path in paths.iter().filter(|p| !p.triangles.is_empty()) {
+        let Some(color) = styles.resolve(&path.tags).fill else {
+            continue;
+        };
+        let first = mesh.vertices.len() as u32;
+        for p in &path.points {
+            let [x, y] = stored_to_plot(origin, *p);
+            mesh.colored_vertex(transform.position_from_point(&PlotPoint::new(x, y)), color);
+        }
+        for [a, b, c] in &path.triangles {
+            mesh.add_triangle(first + *a as u32, first + *b as u32, first + *c as u32);
+        }
+    }
+    mesh
 }
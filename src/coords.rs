@@ -0,0 +1,302 @@
+use std::fmt;
+
+/// Mean earth radius, as used by the haversine formula
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Length of a degree of latitude
+pub const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// A position on the globe in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoCoord {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl GeoCoord {
+    pub fn from_latlon(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+
+    /// Like [`GeoCoord::from_latlon`], but rejects coordinates off the globe, NaN included.
+    pub fn try_from_latlon(lat: f64, lon: f64) -> Result<Self, CoordError> {
+        if !(-90.0..=90.0).contains(&lat) {
+            return Err(CoordError::Latitude(lat));
+        }
+        if !(-180.0..=180.0).contains(&lon) {
+            return Err(CoordError::Longitude(lon));
+        }
+        Ok(Self::from_latlon(lat, lon))
+    }
+
+    pub fn to_latlon(&self) -> (f64, f64) {
+        (self.lat, self.lon)
+    }
+
+    /// From a point as stored in paths: decimicro degrees, `(-lon, -lat)`.
+    pub fn from_stored(point: (i32, i32)) -> Self {
+        Self::from_latlon(-point.1 as f64 * 1e-7, -point.0 as f64 * 1e-7)
+    }
+
+    /// Inverse of [`GeoCoord::from_stored`], rounded to the nearest decimicro degree.
+    pub fn to_stored(&self) -> (i32, i32) {
+        (
+            -(self.lon * 1e7).round() as i32,
+            -(self.lat * 1e7).round() as i32,
+        )
+    }
+
+    /// Great-circle distance in meters, by the haversine formula.
+    /// Longitudes only appear as a difference inside a sine, so crossing the antimeridian is fine.
+    pub fn distance_to(&self, other: &GeoCoord) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.lon - self.lon).to_radians();
+        let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        // Rounding can push `h` a hair above 1 for antipodal points
+        2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoordError {
+    Latitude(f64),
+    Longitude(f64),
+}
+
+impl fmt::Display for CoordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoordError::Latitude(lat) => write!(f, "latitude {lat} is outside -90..=90"),
+            CoordError::Longitude(lon) => write!(f, "longitude {lon} is outside -180..=180"),
+        }
+    }
+}
+
+impl std::error::Error for CoordError {}
+
+/// An extent in lat/lon space. Does not handle the antimeridian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoBoundingBox {
+    pub min: GeoCoord,
+    pub max: GeoCoord,
+}
+
+impl GeoBoundingBox {
+    /// Smallest box holding all of `coords`, `None` when there are none.
+    pub fn from_coords(coords: impl IntoIterator<Item = GeoCoord>) -> Option<Self> {
+        let mut coords = coords.into_iter();
+        let first = coords.next()?;
+        Some(coords.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |b, c| Self {
+                min: GeoCoord::from_latlon(b.min.lat.min(c.lat), b.min.lon.min(c.lon)),
+                max: GeoCoord::from_latlon(b.max.lat.max(c.lat), b.max.lon.max(c.lon)),
+            },
+        ))
+    }
+
+    pub fn center(&self) -> GeoCoord {
+        GeoCoord::from_latlon(
+            (self.min.lat + self.max.lat) / 2.0,
+            (self.min.lon + self.max.lon) / 2.0,
+        )
+    }
+
+    /// Edges count as inside.
+    pub fn contains(&self, p: &GeoCoord) -> bool {
+        (self.min.lat..=self.max.lat).contains(&p.lat)
+            && (self.min.lon..=self.max.lon).contains(&p.lon)
+    }
+
+    /// Boxes sharing only an edge or a corner intersect.
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.lat <= other.max.lat
+            && other.min.lat <= self.max.lat
+            && self.min.lon <= other.max.lon
+            && other.min.lon <= self.max.lon
+    }
+
+    /// Smallest box holding both.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: GeoCoord::from_latlon(
+                self.min.lat.min(other.min.lat),
+                self.min.lon.min(other.min.lon),
+            ),
+            max: GeoCoord::from_latlon(
+                self.max.lat.max(other.max.lat),
+                self.max.lon.max(other.max.lon),
+            ),
+        }
+    }
+}
+
+/// Meters one screen point spans east-west at `lat`, in a view showing `degrees_per_point`
+/// degrees of longitude per point. Meridians converge towards the poles, hence the cosine:
+/// at 60°N a point covers half as much ground as at the equator.
+pub fn meters_per_point(lat: f64, degrees_per_point: f64) -> f64 {
+    degrees_per_point * METERS_PER_DEGREE * lat.to_radians().cos()
+}
+
+/// Latitude where the square Web Mercator world ends
+pub const MAX_MERCATOR_LAT: f64 = 85.051_128_779_806_59;
+
+/// Deepest tile zoom, where tile numbers still fit a `u32`. Deeper zooms are clamped to it.
+pub const MAX_TILE_ZOOM: u8 = 32;
+
+/// Slippy-map tile `(x, y)` holding the point at `zoom`, as in the OSM tile scheme.
+/// Latitudes past [`MAX_MERCATOR_LAT`] land in the top or bottom row.
+pub fn tile_xy(lat: f64, lon: f64, zoom: u8) -> (u32, u32) {
+    let n = (1u64 << zoom.min(MAX_TILE_ZOOM)) as f64;
+    let lat = lat.clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT).to_radians();
+    let x = (lon + 180.0) / 360.0 * n;
+    let y = (1.0 - lat.tan().asinh() / std::f64::consts::PI) / 2.0 * n;
+    let last = n - 1.0;
+    (x.clamp(0.0, last) as u32, y.clamp(0.0, last) as u32)
+}
+
+/// Extent of the slippy-map tile `z/x/y`.
+/// Tile numbers are counted in floats, so the far edge of tile `u32::MAX` does not overflow.
+pub fn tile_bounds(z: u8, x: u32, y: u32) -> GeoBoundingBox {
+    let n = (1u64 << z.min(MAX_TILE_ZOOM)) as f64;
+    let lon = |x: f64| x / n * 360.0 - 180.0;
+    let lat = |y: f64| {
+        (std::f64::consts::PI * (1.0 - 2.0 * y / n))
+            .sinh()
+            .atan()
+            .to_degrees()
+    };
+    let (x, y) = (x as f64, y as f64);
+    GeoBoundingBox {
+        min: GeoCoord::from_latlon(lat(y + 1.0), lon(x)),
+        max: GeoCoord::from_latlon(lat(y), lon(x + 1.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn helsinki_to_tallinn() {
+        // City centres, about 82 km apart across the gulf
+        let helsinki = GeoCoord::from_latlon(60.1699, 24.9384);
+        let tallinn = GeoCoord::from_latlon(59.4370, 24.7536);
+        assert_near(helsinki.distance_to(&tallinn), 82_150.0, 300.0);
+        assert_eq!(
+            helsinki.distance_to(&tallinn),
+            tallinn.distance_to(&helsinki)
+        );
+    }
+
+    #[test]
+    fn across_the_antimeridian() {
+        // One degree along the equator, not 359
+        let east = GeoCoord::from_latlon(0.0, 179.5);
+        let west = GeoCoord::from_latlon(0.0, -179.5);
+        assert_near(east.distance_to(&west), 111_195.0, 1.0);
+    }
+
+    #[test]
+    fn over_the_pole() {
+        // Opposite meridians 0.1° from the pole are 0.2° of arc apart
+        let a = GeoCoord::from_latlon(89.9, 0.0);
+        let b = GeoCoord::from_latlon(89.9, 180.0);
+        assert_near(a.distance_to(&b), 22_239.0, 1.0);
+
+        let north = GeoCoord::from_latlon(90.0, 0.0);
+        let south = GeoCoord::from_latlon(-90.0, 0.0);
+        assert_near(
+            north.distance_to(&south),
+            std::f64::consts::PI * EARTH_RADIUS_M,
+            1e-6,
+        );
+    }
+
+    #[test]
+    fn zoom_zero_is_one_tile() {
+        for (lat, lon) in [(0.0, 0.0), (60.17, 24.94), (-85.0, -180.0), (89.0, 180.0)] {
+            assert_eq!(tile_xy(lat, lon, 0), (0, 0));
+        }
+        let world = tile_bounds(0, 0, 0);
+        assert_near(world.min.lon, -180.0, 1e-9);
+        assert_near(world.max.lon, 180.0, 1e-9);
+        assert_near(world.min.lat, -MAX_MERCATOR_LAT, 1e-9);
+        assert_near(world.max.lat, MAX_MERCATOR_LAT, 1e-9);
+    }
+
+    #[test]
+    fn helsinki_at_zoom_12() {
+        // tile.openstreetmap.org/12/2331/1185.png
+        assert_eq!(tile_xy(60.1699, 24.9384, 12), (2331, 1185));
+        let tile = tile_bounds(12, 2331, 1185);
+        assert!(tile.contains(&GeoCoord::from_latlon(60.1699, 24.9384)));
+    }
+
+    #[test]
+    fn deep_zooms_and_last_tiles_do_not_overflow() {
+        assert_eq!(tile_xy(90.0, 180.0, u8::MAX), (u32::MAX, 0));
+        let last = tile_bounds(u8::MAX, u32::MAX, u32::MAX);
+        assert_near(last.max.lon, 180.0, 1e-6);
+    }
+
+    fn bbox(min: (f64, f64), max: (f64, f64)) -> GeoBoundingBox {
+        GeoBoundingBox {
+            min: GeoCoord::from_latlon(min.0, min.1),
+            max: GeoCoord::from_latlon(max.0, max.1),
+        }
+    }
+
+    #[test]
+    fn edges_and_corners_are_inside() {
+        let b = bbox((60.0, 24.0), (61.0, 25.0));
+        assert!(b.contains(&GeoCoord::from_latlon(60.0, 24.5)));
+        assert!(b.contains(&GeoCoord::from_latlon(60.5, 25.0)));
+        assert!(b.contains(&GeoCoord::from_latlon(61.0, 24.0)));
+        assert!(!b.contains(&GeoCoord::from_latlon(61.000_001, 24.5)));
+        assert!(!b.contains(&GeoCoord::from_latlon(60.5, 23.999_999)));
+    }
+
+    #[test]
+    fn boxes_touching_at_an_edge_or_corner_intersect() {
+        let b = bbox((60.0, 24.0), (61.0, 25.0));
+        let east = bbox((60.0, 25.0), (61.0, 26.0));
+        let corner = bbox((61.0, 25.0), (62.0, 26.0));
+        let apart = bbox((61.000_001, 24.0), (62.0, 25.0));
+        assert!(b.intersects(&east) && east.intersects(&b));
+        assert!(b.intersects(&corner) && corner.intersects(&b));
+        assert!(!b.intersects(&apart) && !apart.intersects(&b));
+        assert!(b.intersects(&b));
+    }
+
+    #[test]
+    fn union_of_touching_boxes() {
+        let b = bbox((60.0, 24.0), (61.0, 25.0));
+        let east = bbox((60.0, 25.0), (61.0, 26.0));
+        assert_eq!(b.union(&east), bbox((60.0, 24.0), (61.0, 26.0)));
+        assert_eq!(b.union(&east), east.union(&b));
+        assert_eq!(b.union(&b), b);
+        let inner = bbox((60.2, 24.2), (60.8, 24.8));
+        assert_eq!(b.union(&inner), b);
+    }
+
+    #[test]
+    fn a_point_covers_half_the_ground_at_60_north() {
+        let dpp = 1e-5;
+        let equator = meters_per_point(0.0, dpp);
+        assert_near(equator, dpp * METERS_PER_DEGREE, 1e-12);
+        assert_near(meters_per_point(60.0, dpp), equator / 2.0, 1e-9);
+        assert_near(meters_per_point(-60.0, dpp), equator / 2.0, 1e-9);
+    }
+}
@@ -1,6 +1,16 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
-mod zana;
+pub mod coords;
+pub mod permalink;
+mod style;
+mod svg;
+pub mod zana;
 
 pub use app::TemplateApp;
+pub use coords::{CoordError, GeoBoundingBox, GeoCoord};
+pub use style::{auto_color_for_tag, PaintStyle, StyleSheet};
+pub use svg::draw_svg;
+#[cfg(feature = "geojson")]
+pub use zana::{from_geojson, to_geojson};
+pub use zana::{LoadOptions, MapData, Path, Poi, WayFilter};
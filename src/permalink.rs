@@ -0,0 +1,129 @@
+//! The view as a `#map=zoom/lat/lon` URL fragment, the same format as openstreetmap.org,
+//! so a link to the page opens on the same spot.
+//! Only the browser has an address bar, elsewhere just the format is here.
+
+use crate::coords::GeoCoord;
+#[cfg(target_arch = "wasm32")]
+use std::time::Duration;
+
+/// How long the view has to stay put before the address bar follows
+#[cfg(target_arch = "wasm32")]
+const DEBOUNCE: f64 = 0.5;
+
+/// Parses `#map=zoom/lat/lon`, the leading `#` is optional.
+pub fn parse(hash: &str) -> Option<(f64, GeoCoord)> {
+    let fields = hash.trim_start_matches('#').strip_prefix("map=")?;
+    let fields: Vec<f64> = fields
+        .split('/')
+        .map(|f| f.parse().ok())
+        .collect::<Option<_>>()?;
+    let [zoom, lat, lon] = fields[..] else {
+        return None;
+    };
+    if !zoom.is_finite() {
+        return None;
+    }
+    Some((zoom, GeoCoord::try_from_latlon(lat, lon).ok()?))
+}
+
+/// Five decimals are about a meter, which is as close as anyone will want to share.
+pub fn format(zoom: f64, center: GeoCoord) -> String {
+    format!("#map={zoom:.2}/{:.5}/{:.5}", center.lat, center.lon)
+}
+
+/// Keeps the address bar in step with the view.
+/// Writes wait until the view settles and replace the history entry, so panning does not
+/// fill the back button with every intermediate view.
+#[cfg(target_arch = "wasm32")]
+#[derive(Default)]
+pub struct Permalink {
+    written: String,
+    pending: Option<(String, f64)>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Permalink {
+    pub fn update(&mut self, ctx: &egui::Context, zoom: f64, center: GeoCoord) {
+        let hash = format(zoom, center);
+        if hash == self.written {
+            self.pending = None;
+            return;
+        }
+        let now = ctx.input(|i| i.time);
+        let since = match &self.pending {
+            Some((pending, since)) if *pending == hash => *since,
+            _ => now,
+        };
+        if now - since >= DEBOUNCE {
+            replace_hash(&hash);
+            self.written = hash;
+            self.pending = None;
+        } else {
+            // Nothing else may repaint once the view stops moving
+            ctx.request_repaint_after(Duration::from_secs_f64(DEBOUNCE - (now - since)));
+            self.pending = Some((hash, since));
+        }
+    }
+}
+
+/// Fragment index.html checks to skip the service worker during development.
+/// Overwriting it would turn caching back on at the next reload, so it is left alone.
+#[cfg(target_arch = "wasm32")]
+const DEV_HASH: &str = "#dev";
+
+#[cfg(target_arch = "wasm32")]
+fn replace_hash(hash: &str) {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    if matches!(window.location().hash().as_deref(), Ok(DEV_HASH)) {
+        return;
+    }
+    let Ok(history) = window.history() else {
+        return;
+    };
+    if let Err(e) = history.replace_state_with_url(&wasm_bindgen::JsValue::NULL, "", Some(hash)) {
+        log::warn!("Could not update the URL: {e:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_and_parse_round_trip() {
+        let hash = format(12.5, GeoCoord::from_latlon(60.16987, 24.93838));
+        assert_eq!(hash, "#map=12.50/60.16987/24.93838");
+        let (zoom, center) = parse(&hash).unwrap();
+        assert_eq!(zoom, 12.5);
+        assert_eq!(center, GeoCoord::from_latlon(60.16987, 24.93838));
+    }
+
+    #[test]
+    fn leading_hash_is_optional() {
+        assert_eq!(parse("map=3/-33.9/18.4"), parse("#map=3/-33.9/18.4"));
+        assert!(parse("map=3/-33.9/18.4").is_some());
+    }
+
+    #[test]
+    fn off_globe_coordinates_are_rejected() {
+        assert_eq!(parse("#map=10/91/24"), None);
+        assert_eq!(parse("#map=10/60/-181"), None);
+        assert_eq!(parse("#map=10/NaN/24"), None);
+    }
+
+    #[test]
+    fn wrong_field_counts_are_rejected() {
+        assert_eq!(parse("#map=10/60"), None);
+        assert_eq!(parse("#map=10/60/24/1"), None);
+        assert_eq!(parse("#map="), None);
+        assert_eq!(parse("#dev"), None);
+    }
+
+    #[test]
+    fn non_finite_zoom_is_rejected() {
+        assert_eq!(parse("#map=inf/60/24"), None);
+        assert_eq!(parse("#map=NaN/60/24"), None);
+    }
+}
@@ -0,0 +1,264 @@
+use egui::{ecolor::Hsva, Color32};
+use osmpbfreader::Tags;
+use std::collections::HashMap;
+
+/// Meters per screen point at which a [`PaintStyle`] has exactly its `width`.
+pub const REFERENCE_SCALE: f64 = 2.0;
+
+/// Shortest dash in screen points. egui splits lines into dashes one by one,
+/// and ever shorter ones would keep it from finishing the frame.
+pub const MIN_DASH: f32 = 1.0;
+
+/// How a single path is stroked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaintStyle {
+    pub color: Color32,
+    /// Line width in screen points at [`REFERENCE_SCALE`]
+    pub width: f32,
+    /// Bounds for the width when zooming, equal to `width` for a fixed width
+    pub min_width: f32,
+    pub max_width: f32,
+    /// Fill for closed ways
+    pub fill: Option<Color32>,
+    /// Dash length in screen points for dashed lines, see [`PaintStyle::dashed`]
+    dash: Option<f32>,
+    /// Paths with a higher z-index are drawn on top, within the same OSM layer
+    pub z_index: i32,
+}
+
+impl PaintStyle {
+    pub fn new(color: Color32, width: f32) -> Self {
+        Self {
+            color,
+            width,
+            min_width: width,
+            max_width: width,
+            fill: None,
+            dash: None,
+            z_index: 0,
+        }
+    }
+
+    /// Draws the line dashed, with dashes `length` points long and gaps sized by egui.
+    /// Lengths that are not positive and finite are ignored, shorter than [`MIN_DASH`] are raised.
+    pub fn dashed(mut self, length: f32) -> Self {
+        self.dash = (length.is_finite() && length > 0.0).then_some(length.max(MIN_DASH));
+        self
+    }
+
+    /// Dash length in screen points, `None` for solid lines.
+    pub fn dash(&self) -> Option<f32> {
+        self.dash
+    }
+
+    pub fn z_index(mut self, z_index: i32) -> Self {
+        self.z_index = z_index;
+        self
+    }
+
+    /// Lets the width follow the zoom between `min_width` and `max_width`.
+    pub fn scaled(mut self, min_width: f32, max_width: f32) -> Self {
+        self.min_width = min_width;
+        self.max_width = max_width;
+        self
+    }
+
+    /// Line width when one screen point covers `meters_per_point` meters.
+    pub fn width_for_scale(&self, meters_per_point: f64) -> f32 {
+        let width = (self.width as f64 * REFERENCE_SCALE / meters_per_point) as f32;
+        // Not `clamp`, which panics on a NaN or on bounds the wrong way round
+        width.max(self.min_width).min(self.max_width)
+    }
+
+    pub fn filled(mut self, fill: Color32) -> Self {
+        self.fill = Some(fill);
+        self
+    }
+}
+
+/// Maps tags to paint styles.
+///
+/// A rule is either for a whole key (`highway`) or for a key and value (`highway=motorway`).
+/// A path takes the first key-and-value rule matching one of its tags, then the first key rule,
+/// and the default style if nothing matches.
+#[derive(Debug, Clone)]
+pub struct StyleSheet {
+    rules: HashMap<String, KeyRules>,
+    pub default: PaintStyle,
+}
+
+#[derive(Debug, Clone, Default)]
+struct KeyRules {
+    any_value: Option<PaintStyle>,
+    by_value: HashMap<String, PaintStyle>,
+}
+
+impl StyleSheet {
+    pub fn new(default: PaintStyle) -> Self {
+        Self {
+            rules: HashMap::new(),
+            default,
+        }
+    }
+
+    /// Adds a rule for `key`, or only for `key=value` when a value is given.
+    pub fn with(mut self, key: &str, value: Option<&str>, style: PaintStyle) -> Self {
+        self.insert(key, value, style);
+        self
+    }
+
+    pub fn insert(&mut self, key: &str, value: Option<&str>, style: PaintStyle) {
+        let rules = self.rules.entry(key.to_owned()).or_default();
+        match value {
+            Some(v) => {
+                rules.by_value.insert(v.to_owned(), style);
+            }
+            None => rules.any_value = Some(style),
+        }
+    }
+
+    /// Whether a rule, rather than the default style, covers one of `tags`.
+    /// The loader keeps exactly these ways, so a rule is all a new kind of feature needs.
+    pub fn has_rule(&self, tags: &Tags) -> bool {
+        tags.iter().any(|(k, v)| {
+            self.rules.get(k.as_str()).map_or(false, |r| {
+                r.any_value.is_some() || r.by_value.contains_key(v.as_str())
+            })
+        })
+    }
+
+    pub fn resolve(&self, tags: &Tags) -> &PaintStyle {
+        let matching = || {
+            tags.iter()
+                .filter_map(|(k, v)| Some((self.rules.get(k.as_str())?, v)))
+        };
+        matching()
+            .find_map(|(rules, v)| rules.by_value.get(v.as_str()))
+            .or_else(|| matching().find_map(|(rules, _)| rules.any_value.as_ref()))
+            .unwrap_or(&self.default)
+    }
+}
+
+impl Default for StyleSheet {
+    fn default() -> Self {
+        // Painter's order: water, then (future) landuse and buildings, then roads, wider on top,
+        // then boundaries over everything
+        const WATER: i32 = -300;
+        const ROADS: i32 = 100;
+        const BOUNDARIES: i32 = 1000;
+        let road = |r, g, b, width: f32| {
+            PaintStyle::new(Color32::from_rgb(r, g, b), width)
+                .scaled(width / 4.0, width * 4.0)
+                .z_index(ROADS + (width * 10.0) as i32)
+        };
+        // For POIs the width is the marker radius
+        let marker = |r, g, b, radius: f32| {
+            PaintStyle::new(Color32::from_rgb(r, g, b), radius).scaled(1.0, radius * 2.0)
+        };
+        let mut styles = Self::new(PaintStyle::new(Color32::GRAY, 1.0))
+            .with("highway", None, road(200, 200, 200, 2.0))
+            .with(
+                "natural",
+                Some("water"),
+                PaintStyle::new(Color32::from_rgb(60, 130, 220), 1.0)
+                    .filled(Color32::from_rgb(60, 130, 220))
+                    .z_index(WATER),
+            )
+            .with(
+                "natural",
+                Some("coastline"),
+                PaintStyle::new(Color32::from_rgb(20, 70, 160), 1.5).z_index(WATER),
+            )
+            .with(
+                "boundary",
+                Some("administrative"),
+                PaintStyle::new(Color32::from_rgb(160, 90, 170), 1.5)
+                    .dashed(6.0)
+                    .z_index(BOUNDARIES),
+            );
+        for (key, value, style) in [
+            ("amenity", None, marker(200, 80, 80, 3.0)),
+            ("shop", None, marker(170, 80, 200, 3.0)),
+            ("tourism", None, marker(80, 160, 200, 3.0)),
+            ("natural", Some("tree"), marker(60, 160, 60, 2.0)),
+        ] {
+            styles.insert(key, value, style);
+        }
+        for (class, style) in [
+            ("motorway", road(230, 130, 150, 6.0)),
+            ("trunk", road(250, 170, 140, 5.0)),
+            ("primary", road(250, 210, 160, 4.5)),
+            ("secondary", road(245, 245, 185, 4.0)),
+            ("tertiary", road(230, 230, 230, 3.5)),
+            ("residential", road(210, 210, 210, 3.0)),
+            ("service", road(190, 190, 190, 1.5)),
+            ("footway", road(250, 130, 110, 1.0)),
+            ("cycleway", road(100, 100, 250, 1.0)),
+            ("construction", road(200, 200, 200, 2.0).dashed(6.0)),
+            ("proposed", road(200, 200, 200, 2.0).dashed(6.0)),
+        ] {
+            styles.insert("highway", Some(class), style);
+        }
+        styles
+    }
+}
+
+/// A stable colour for a tag key, the same on every run and platform.
+pub fn auto_color_for_tag(key: &str) -> Color32 {
+    // FNV-1a, because the std hasher is not guaranteed to stay the same between releases
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    let hue = (hash % 360) as f32 / 360.0;
+    Hsva::new(hue, 0.75, 0.8, 1.0).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dashes_are_never_too_short_to_draw() {
+        let line = PaintStyle::new(Color32::GRAY, 2.0);
+        assert_eq!(line.clone().dashed(6.0).dash(), Some(6.0));
+        assert_eq!(line.clone().dashed(0.01).dash(), Some(MIN_DASH));
+        for length in [0.0, -3.0, f32::NAN, f32::INFINITY] {
+            assert_eq!(line.clone().dashed(length).dash(), None, "{length}");
+        }
+    }
+
+    #[test]
+    fn administrative_boundaries_are_dashed() {
+        let tags: Tags = [("boundary".into(), "administrative".into())]
+            .into_iter()
+            .collect();
+        let styles = StyleSheet::default();
+        assert!(styles.has_rule(&tags));
+        assert_eq!(styles.resolve(&tags).dash(), Some(6.0));
+    }
+
+    #[test]
+    fn width_is_exact_at_the_reference_scale() {
+        let road = PaintStyle::new(Color32::GRAY, 3.0).scaled(1.0, 12.0);
+        assert_eq!(road.width_for_scale(REFERENCE_SCALE), 3.0);
+        // Twice the ground per point, half the width
+        assert_eq!(road.width_for_scale(REFERENCE_SCALE * 2.0), 1.5);
+    }
+
+    #[test]
+    fn width_is_clamped_at_extreme_scales() {
+        let road = PaintStyle::new(Color32::GRAY, 3.0).scaled(1.0, 12.0);
+        assert_eq!(road.width_for_scale(1e-6), 12.0);
+        assert_eq!(road.width_for_scale(1e6), 1.0);
+        assert_eq!(road.width_for_scale(0.0), 12.0);
+        let fixed = PaintStyle::new(Color32::GRAY, 3.0);
+        assert_eq!(fixed.width_for_scale(1e6), 3.0);
+    }
+
+    #[test]
+    fn nan_scale_does_not_panic() {
+        let road = PaintStyle::new(Color32::GRAY, 3.0).scaled(1.0, 12.0);
+        let width = road.width_for_scale(f64::NAN);
+        assert!((1.0..=12.0).contains(&width), "{width}");
+    }
+}
@@ -0,0 +1,162 @@
+//! Vector output of the map, e.g. for print, drawn the way the app draws it.
+
+use crate::coords::{GeoBoundingBox, GeoCoord, METERS_PER_DEGREE};
+use crate::style::StyleSheet;
+use crate::zana::MapData;
+use egui::Color32;
+
+/// The part of the map inside `bbox` as an SVG document `width` points wide.
+///
+/// As in the app, a degree of longitude is as long as a degree of latitude, line widths follow
+/// the scale and fills go under all lines. Only features touching `bbox` are written, the
+/// viewport hides what sticks out of it. A `bbox` without area gives an empty document.
+pub fn draw_svg(data: &MapData, bbox: &GeoBoundingBox, styles: &StyleSheet, width: f64) -> String {
+    let (lon_span, lat_span) = (bbox.max.lon - bbox.min.lon, bbox.max.lat - bbox.min.lat);
+    // There is no scale to draw at, and dividing by the span would write inf and NaN
+    if !(lon_span > 0.0 && lat_span > 0.0 && width > 0.0 && width.is_finite()) {
+        return "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"0\" height=\"0\"/>\n".into();
+    }
+    let degrees_per_point = lon_span / width;
+    let height = lat_span / degrees_per_point;
+    let meters_per_point = degrees_per_point * METERS_PER_DEGREE;
+    let project = |p: &(i32, i32)| {
+        let c = GeoCoord::from_stored(*p);
+        (
+            (c.lon - bbox.min.lon) / degrees_per_point,
+            (bbox.max.lat - c.lat) / degrees_per_point,
+        )
+    };
+    let path_data = |points: &[(i32, i32)]| {
+        let mut d = String::new();
+        for (i, p) in points.iter().enumerate() {
+            let (x, y) = project(p);
+            d.push_str(&format!("{}{x:.2} {y:.2}", if i == 0 { "M" } else { " L" }));
+        }
+        d
+    };
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\">\n"
+    );
+    let mut paths: Vec<_> = data
+        .paths
+        .iter()
+        .filter(|p| p.bounds.map_or(false, |b| b.intersects(bbox)))
+        .collect();
+    paths.sort_by_cached_key(|p| (p.layer(), styles.resolve(&p.tags).z_index));
+    for path in paths.iter().filter(|p| p.is_closed()) {
+        if let Some(fill) = styles.resolve(&path.tags).fill {
+            svg.push_str(&format!(
+                "<path d=\"{}Z\" {}/>\n",
+                path_data(&path.points),
+                paint("fill", fill)
+            ));
+        }
+    }
+    for path in &paths {
+        let style = styles.resolve(&path.tags);
+        // Same gaps as egui's dashed lines
+        let dash = style.dash().map_or_else(String::new, |length| {
+            let gap = length * (5f32.sqrt() - 1.0) / 2.0;
+            format!(" stroke-dasharray=\"{length} {gap}\"")
+        });
+        svg.push_str(&format!(
+            "<path d=\"{}\" fill=\"none\" {} stroke-width=\"{}\" stroke-linejoin=\"round\"{dash}/>\n",
+            path_data(&path.points),
+            paint("stroke", style.color),
+            style.width_for_scale(meters_per_point),
+        ));
+    }
+    for poi in data
+        .pois
+        .iter()
+        .filter(|p| bbox.contains(&GeoCoord::from_stored(p.point)))
+    {
+        let style = styles.resolve(&poi.tags);
+        let (x, y) = project(&poi.point);
+        svg.push_str(&format!(
+            "<circle cx=\"{x:.2}\" cy=\"{y:.2}\" r=\"{}\" {}/>\n",
+            style.width_for_scale(meters_per_point),
+            paint("fill", style.color)
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A colour as SVG attributes, with the opacity separate for SVG 1.1 viewers.
+fn paint(attribute: &str, color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let mut paint = format!("{attribute}=\"rgb({r},{g},{b})\"");
+    if a != u8::MAX {
+        paint.push_str(&format!(" {attribute}-opacity=\"{:.3}\"", a as f32 / 255.0));
+    }
+    paint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zana::{Path, Poi};
+    use osmpbfreader::Tags;
+
+    fn road(from: GeoCoord, to: GeoCoord) -> Path {
+        let points = vec![from.to_stored(), to.to_stored()];
+        Path {
+            bounds: GeoBoundingBox::from_coords([from, to]),
+            points,
+            tags: [("highway".into(), "primary".into())].into_iter().collect(),
+            triangles: vec![],
+        }
+    }
+
+    fn helsinki() -> MapData {
+        let cafe: Tags = [("amenity".into(), "cafe".into())].into_iter().collect();
+        MapData {
+            paths: vec![road(
+                GeoCoord::from_latlon(60.16, 24.93),
+                GeoCoord::from_latlon(60.17, 24.95),
+            )],
+            pois: vec![Poi {
+                point: GeoCoord::from_latlon(60.165, 24.94).to_stored(),
+                tags: cafe,
+            }],
+        }
+    }
+
+    #[test]
+    fn box_without_area_draws_nothing() {
+        let corner = GeoCoord::from_latlon(60.16, 24.93);
+        let flat = GeoBoundingBox {
+            min: corner,
+            max: GeoCoord::from_latlon(60.16, 24.95),
+        };
+        let thin = GeoBoundingBox {
+            min: corner,
+            max: GeoCoord::from_latlon(60.17, 24.93),
+        };
+        for bbox in [flat, thin] {
+            let svg = draw_svg(&helsinki(), &bbox, &StyleSheet::default(), 800.0);
+            assert!(!svg.contains("inf") && !svg.contains("NaN"), "{svg}");
+            assert!(!svg.contains("<path") && !svg.contains("<circle"), "{svg}");
+        }
+    }
+
+    #[test]
+    fn only_what_touches_the_box_is_written() {
+        let around = GeoBoundingBox {
+            min: GeoCoord::from_latlon(60.15, 24.92),
+            max: GeoCoord::from_latlon(60.18, 24.96),
+        };
+        let svg = draw_svg(&helsinki(), &around, &StyleSheet::default(), 800.0);
+        assert!(svg.contains("<path") && svg.contains("<circle"), "{svg}");
+
+        let tallinn = GeoBoundingBox {
+            min: GeoCoord::from_latlon(59.42, 24.72),
+            max: GeoCoord::from_latlon(59.45, 24.77),
+        };
+        let svg = draw_svg(&helsinki(), &tallinn, &StyleSheet::default(), 800.0);
+        assert!(!svg.contains("<path") && !svg.contains("<circle"), "{svg}");
+    }
+}
@@ -1,46 +1,1114 @@
-use osmpbfreader::OsmPbfReader;
+use crate::coords::{GeoBoundingBox, GeoCoord, METERS_PER_DEGREE};
+use crate::style::StyleSheet;
 use eframe::epaint::ahash::HashSet;
+use osmpbfreader::{OsmObj, OsmPbfReader, Tags};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct Path {
     pub points: Vec<(i32, i32)>,
+    pub tags: Tags,
+    /// Triangles covering the inside of a closed way, as indices into `points`.
+    /// Empty for open ways and degenerate rings.
+    pub triangles: Vec<[usize; 3]>,
+    /// Extent of `points`, kept so paths out of view are cheap to skip. `None` without points.
+    pub bounds: Option<GeoBoundingBox>,
 }
 
-pub fn read_nodes_from_file() -> Vec<Path> {
-    let mut reader = OsmPbfReader::new(std::fs::File::open("uusima.pbf").unwrap());
+impl Path {
+    /// The key of the tag that made this way a map feature, e.g. `highway`.
+    pub fn feature_key(&self) -> Option<&str> {
+        FEATURE_KEYS
+            .iter()
+            .find(|k| self.tags.contains_key(**k))
+            .copied()
+    }
+
+    pub fn tag_value(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(|v| v.as_str())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.points.len() >= 4 && self.points.first() == self.points.last()
+    }
+
+    pub fn is_water(&self) -> bool {
+        self.tags.contains("natural", "water")
+    }
+
+    pub fn is_coastline(&self) -> bool {
+        self.tags.contains("natural", "coastline")
+    }
+
+    /// Vertical stacking from the OSM `layer` tag: bridges are above 0, tunnels below.
+    /// Missing or malformed values count as ground level.
+    pub fn layer(&self) -> i32 {
+        self.tags
+            .get("layer")
+            .and_then(|l| l.trim().parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+fn is_poi(tags: &Tags) -> bool {
+    ["amenity", "shop", "tourism"]
+        .iter()
+        .any(|k| tags.contains_key(*k))
+        || tags.contains("natural", "tree")
+}
+
+/// Keys [`is_poi`] looks at, in the order [`Poi::feature_key`] picks them
+const POI_KEYS: &[&str] = &["amenity", "shop", "tourism", "natural"];
+
+/// The path passing closest to `target` and its distance in meters.
+/// Ties go to the path that comes first.
+pub fn nearest_path<'a>(
+    paths: impl IntoIterator<Item = &'a Path>,
+    target: &GeoCoord,
+) -> Option<(&'a Path, f64)> {
+    nearest_by(paths, |path| path_distance(path, target))
+}
+
+/// The POI closest to `target` if it is within `max_dist_m` meters, and its distance.
+/// Ties go to the POI that comes first.
+pub fn nearest_poi<'a>(
+    pois: &'a [Poi],
+    target: &GeoCoord,
+    max_dist_m: f64,
+) -> Option<(&'a Poi, f64)> {
+    nearest_by(pois, |poi| {
+        GeoCoord::from_stored(poi.point).distance_to(target)
+    })
+    .filter(|(_, distance)| *distance <= max_dist_m)
+}
+
+/// A plain scan over everything, the place for a spatial index once the data outgrows it.
+fn nearest_by<'a, T: 'a>(
+    items: impl IntoIterator<Item = &'a T>,
+    distance: impl Fn(&T) -> f64,
+) -> Option<(&'a T, f64)> {
+    let mut nearest: Option<(&T, f64)> = None;
+    for item in items {
+        let distance = distance(item);
+        if nearest.map_or(true, |(_, d)| distance < d) {
+            nearest = Some((item, distance));
+        }
+    }
+    nearest
+}
+
+/// A circle enclosing every point of `paths`, as its center and radius in meters.
+/// It is centered on the middle of the coordinate extent, so it is not the smallest one,
+/// but it is cheap and close enough for framing the data. Data across the antimeridian is
+/// centered on it rather than on the far side of the globe.
+pub fn bounding_circle(paths: &[Path]) -> Option<(GeoCoord, f64)> {
+    let points = || {
+        paths
+            .iter()
+            .flat_map(|p| p.points.iter().map(|p| GeoCoord::from_stored(*p)))
+    };
+    let extent = GeoBoundingBox::from_coords(points())?;
+    // The same points with longitudes counted 0..360, narrower for data across the antimeridian
+    let east = GeoBoundingBox::from_coords(
+        points().map(|p| GeoCoord::from_latlon(p.lat, p.lon.rem_euclid(360.0))),
+    )?;
+    let span = |b: &GeoBoundingBox| b.max.lon - b.min.lon;
+    let center = if span(&east) < span(&extent) {
+        let c = east.center();
+        GeoCoord::from_latlon(c.lat, if c.lon > 180.0 { c.lon - 360.0 } else { c.lon })
+    } else {
+        extent.center()
+    };
+    let radius = points().map(|p| center.distance_to(&p)).fold(0.0, f64::max);
+    Some((center, radius))
+}
+
+fn path_distance(path: &Path, target: &GeoCoord) -> f64 {
+    let points: Vec<_> = path
+        .points
+        .iter()
+        .map(|p| GeoCoord::from_stored(*p))
+        .collect();
+    if points.len() == 1 {
+        return points[0].distance_to(target);
+    }
+    points
+        .windows(2)
+        .map(|s| closest_on_segment(s[0], s[1], target).distance_to(target))
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Closest point of segment `a`-`b` to `target`.
+/// Good enough at the scale of a single road segment, where the earth is flat.
+fn closest_on_segment(a: GeoCoord, b: GeoCoord, target: &GeoCoord) -> GeoCoord {
+    let lon_scale = target.lat.to_radians().cos();
+    let (dx, dy) = ((b.lon - a.lon) * lon_scale, b.lat - a.lat);
+    let (tx, ty) = ((target.lon - a.lon) * lon_scale, target.lat - a.lat);
+    let length2 = dx * dx + dy * dy;
+    if length2 == 0.0 {
+        return a;
+    }
+    let t = ((tx * dx + ty * dy) / length2).clamp(0.0, 1.0);
+    GeoCoord::from_latlon(a.lat + (b.lat - a.lat) * t, a.lon + (b.lon - a.lon) * t)
+}
+
+/// Decides from its tags whether a way is loaded.
+pub type WayFilter = Arc<dyn Fn(&Tags) -> bool + Send + Sync>;
+
+/// Options controlling how ways are turned into [`Path`]s.
+#[derive(Default, Clone)]
+pub struct LoadOptions {
+    /// Drop points lying exactly on the straight segment between their neighbours.
+    /// Lossless for rendering, but straight sections get a lot cheaper to draw.
+    pub remove_collinear: bool,
+    /// Ways are loaded when one of these rules covers them, see [`StyleSheet::has_rule`]
+    pub styles: StyleSheet,
+    /// Loads the ways that pass instead, e.g. just the street network.
+    /// Skipped ways are dropped before their nodes are read, so they cost no memory.
+    pub keep_way: Option<WayFilter>,
+}
+
+impl LoadOptions {
+    /// Whether a way from an extract is loaded.
+    fn keeps(&self, tags: &Tags) -> bool {
+        match &self.keep_way {
+            Some(keep) => keep(tags),
+            None => self.styles.has_rule(tags),
+        }
+    }
+}
+
+impl fmt::Debug for LoadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadOptions")
+            .field("remove_collinear", &self.remove_collinear)
+            .field("styles", &self.styles)
+            .field(
+                "keep_way",
+                &self.keep_way.as_ref().map(|_| "Fn(&Tags) -> bool"),
+            )
+            .finish()
+    }
+}
+
+/// Everything loaded from an extract.
+#[derive(Debug)]
+pub struct MapData {
+    pub paths: Vec<Path>,
+    pub pois: Vec<Poi>,
+}
+
+/// A standalone tagged node such as a shop or a tree, drawn as a marker.
+#[derive(Debug)]
+pub struct Poi {
+    pub point: (i32, i32),
+    pub tags: Tags,
+}
+
+impl Poi {
+    /// The key of the tag that made this node a POI, e.g. `shop`.
+    pub fn feature_key(&self) -> Option<&str> {
+        POI_KEYS
+            .iter()
+            .find(|k| self.tags.contains_key(**k))
+            .copied()
+    }
+}
+
+#[derive(Debug)]
+pub enum ZanaError {
+    Io(std::io::Error),
+    Decode(osmpbfreader::Error),
+}
+
+impl fmt::Display for ZanaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZanaError::Io(e) => write!(f, "could not open the extract: {e}"),
+            ZanaError::Decode(e) => write!(f, "could not decode the extract: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ZanaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ZanaError::Io(e) => Some(e),
+            ZanaError::Decode(e) => Some(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for ZanaError {
+    fn from(e: std::io::Error) -> Self {
+        ZanaError::Io(e)
+    }
+}
+
+impl From<osmpbfreader::Error> for ZanaError {
+    fn from(e: osmpbfreader::Error) -> Self {
+        ZanaError::Decode(e)
+    }
+}
+
+/// Reads the extract, failing on a missing file or a corrupt block instead of panicking.
+pub fn read_nodes_from_file(options: &LoadOptions) -> Result<MapData, ZanaError> {
+    read_nodes(std::fs::File::open("uusima.pbf")?, options)
+}
+
+/// Reads ways and POIs from a PBF stream. It is read twice, ways first, hence the `Seek`.
+pub fn read_nodes(
+    pbf: impl std::io::Read + std::io::Seek,
+    options: &LoadOptions,
+) -> Result<MapData, ZanaError> {
+    let mut reader = OsmPbfReader::new(pbf);
 
     const ROADS: usize = 100000;
     let ways: Vec<_> = reader
         .iter()
-        .filter_map(|o| o.ok())
-        .filter(|o| o.tags().contains_key("highway"))
-        .filter_map(|o| o.way().cloned())
-        .filter(|w| !w.nodes.is_empty())
+        .filter_map(|o| match o {
+            Ok(o) if options.keeps(o.tags()) => {
+                o.way().filter(|w| !w.nodes.is_empty()).cloned().map(Ok)
+            }
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
         .take(ROADS)
-        .collect();
+        .collect::<Result<_, _>>()?;
 
     let nodes_to_read: HashSet<_> = ways.iter().flat_map(|w| w.nodes.clone()).collect();
 
-    reader.rewind().unwrap();
+    reader.rewind()?;
 
-    let node_coordinates: HashMap<_, _> = reader
-        .iter()
-        .filter_map(|o| o.ok())
-        .filter_map(|o| o.node().cloned())
-        .filter(|n| nodes_to_read.contains(&n.id))
-        .map(|n| (n.id, (-n.decimicro_lon, -n.decimicro_lat)))
-        .collect();
+    let mut node_coordinates = HashMap::new();
+    let mut pois = vec![];
+    for o in reader.iter() {
+        let OsmObj::Node(n) = o? else {
+            continue;
+        };
+        // A single broken node should not take the whole extract down with it
+        if let Err(e) = GeoCoord::try_from_latlon(n.lat(), n.lon()) {
+            log::warn!("Skipping node {}: {e}", n.id.0);
+            continue;
+        }
+        let point = (-n.decimicro_lon, -n.decimicro_lat);
+        if nodes_to_read.contains(&n.id) {
+            node_coordinates.insert(n.id, point);
+        }
+        if is_poi(&n.tags) {
+            pois.push(Poi {
+                point,
+                tags: n.tags,
+            });
+        }
+    }
 
-    ways.iter()
+    let paths = ways
+        .iter()
         .map(|w| {
             let points = w
                 .nodes
                 .iter()
                 .filter_map(|n| node_coordinates.get(n).cloned())
                 .collect();
-            Path { points }
+            build_path(points, w.tags.clone(), options)
         })
         .filter(|p| !p.points.is_empty())
+        .collect();
+
+    Ok(MapData { paths, pois })
+}
+
+/// Prepares a way's points for drawing as [`LoadOptions`] asks.
+fn build_path(mut points: Vec<(i32, i32)>, tags: Tags, options: &LoadOptions) -> Path {
+    if options.remove_collinear {
+        points = remove_collinear(&points);
+    }
+    // Outer rings clockwise, so fills can rely on the winding
+    if tags.contains("natural", "water") {
+        ensure_winding(&mut points, true);
+    }
+    let bounds = GeoBoundingBox::from_coords(points.iter().map(|p| GeoCoord::from_stored(*p)));
+    let mut path = Path {
+        points,
+        tags,
+        triangles: vec![],
+        bounds,
+    };
+    if path.is_closed() {
+        path.triangles = triangulate(&path.points);
+    }
+    path
+}
+
+const FEATURE_KEYS: &[&str] = &["highway", "natural"];
+
+/// Twice the signed area of a ring, positive when it runs counter-clockwise.
+/// Stored points are both axes negated, a half-turn, so the sign is the same as in lat/lon.
+pub fn signed_area2(ring: &[(i32, i32)]) -> i64 {
+    if ring.is_empty() {
+        return 0;
+    }
+    let next = ring.iter().cycle().skip(1);
+    ring.iter()
+        .zip(next)
+        .map(|(a, b)| a.0 as i64 * b.1 as i64 - b.0 as i64 * a.1 as i64)
+        .sum()
+}
+
+/// Reverses a closed ring if needed so it runs clockwise (or counter-clockwise).
+/// Open ways and rings without area are left as they are.
+pub fn ensure_winding(ring: &mut [(i32, i32)], clockwise: bool) {
+    if ring.len() < 4 || ring.first() != ring.last() {
+        return;
+    }
+    let area = signed_area2(ring);
+    if area != 0 && (area < 0) != clockwise {
+        ring.reverse();
+    }
+}
+
+/// Splits a closed ring into triangles by ear clipping.
+///
+/// Rings with fewer than 3 distinct points or no area give no triangles.
+/// Self-intersecting rings are covered as far as ears can be found, the rest is left out.
+pub fn triangulate(ring: &[(i32, i32)]) -> Vec<[usize; 3]> {
+    let ring = match ring.split_last() {
+        Some((last, rest)) if rest.first() == Some(last) => rest,
+        _ => ring,
+    };
+    let area = signed_area2(ring);
+    if ring.len() < 3 || area == 0 {
+        return vec![];
+    }
+    let mut remaining: Vec<usize> = (0..ring.len()).collect();
+    let mut triangles = Vec::with_capacity(ring.len() - 2);
+    let (mut i, mut tried) = (0, 0);
+    while remaining.len() > 3 && tried < remaining.len() {
+        let n = remaining.len();
+        let ear = [
+            remaining[(i + n - 1) % n],
+            remaining[i],
+            remaining[(i + 1) % n],
+        ];
+        if is_ear(ring, &remaining, ear, area > 0) {
+            triangles.push(ear);
+            remaining.remove(i);
+            tried = 0;
+        } else {
+            i += 1;
+            tried += 1;
+        }
+        i %= remaining.len();
+    }
+    if remaining.len() == 3 {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+    triangles
+}
+
+fn is_ear(ring: &[(i32, i32)], remaining: &[usize], [a, b, c]: [usize; 3], ccw: bool) -> bool {
+    let cross = |o: (i32, i32), p: (i32, i32), q: (i32, i32)| {
+        let (ux, uy) = delta(o, p);
+        let (vx, vy) = delta(o, q);
+        let cross = ux * vy - uy * vx;
+        if ccw {
+            cross
+        } else {
+            -cross
+        }
+    };
+    let (pa, pb, pc) = (ring[a], ring[b], ring[c]);
+    if cross(pa, pb, pc) <= 0 {
+        return false;
+    }
+    // No other vertex may sit inside the triangle, or cutting it off would cross the ring
+    !remaining.iter().map(|&i| ring[i]).any(|p| {
+        p != pa
+            && p != pb
+            && p != pc
+            && cross(pa, pb, p) >= 0
+            && cross(pb, pc, p) >= 0
+            && cross(pc, pa, p) >= 0
+    })
+}
+
+/// Removes points that lie on the segment between the previous kept point and the next one.
+/// Coordinates are integer decimicro degrees, so the check is exact.
+/// The first and the last point are always kept, so closed ways stay closed.
+pub fn remove_collinear(points: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    let mut result: Vec<(i32, i32)> = Vec::with_capacity(points.len());
+    for (i, &point) in points.iter().enumerate() {
+        let (Some(&prev), Some(&next)) = (result.last(), points.get(i + 1)) else {
+            result.push(point);
+            continue;
+        };
+        let (ax, ay) = delta(prev, point);
+        let (bx, by) = delta(point, next);
+        // Same line and same direction: a way doubling back on itself keeps its turning point
+        let on_segment = ax * by == ay * bx && ax * bx + ay * by > 0;
+        if !on_segment {
+            result.push(point);
+        }
+    }
+    result
+}
+
+fn delta(from: (i32, i32), to: (i32, i32)) -> (i64, i64) {
+    (to.0 as i64 - from.0 as i64, to.1 as i64 - from.1 as i64)
+}
+
+/// Ramer-Douglas-Peucker: drops points as long as the line stays within `tolerance_m` meters
+/// of the original. The first and the last point are always kept, so closed ways stay closed.
+pub fn simplify_path(points: &[(i32, i32)], tolerance_m: f64) -> Vec<(i32, i32)> {
+    // Nothing could be dropped, no need to measure
+    if points.len() < 3 || tolerance_m <= 0.0 {
+        return points.to_vec();
+    }
+    // Flat earth in meters, fine at the scale of a single way
+    let lat = GeoCoord::from_stored(points[0]).lat;
+    let scale = 1e-7 * METERS_PER_DEGREE;
+    let meters = |p: (i32, i32)| {
+        (
+            p.0 as f64 * scale * lat.to_radians().cos(),
+            p.1 as f64 * scale,
+        )
+    };
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    // A stack rather than recursion, long ways would run out of it
+    let mut spans = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = spans.pop() {
+        let (a, b) = (meters(points[first]), meters(points[last]));
+        let farthest = (first + 1..last)
+            .map(|i| (i, segment_distance(a, b, meters(points[i]))))
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance_m {
+                keep[i] = true;
+                spans.push((first, i));
+                spans.push((i, last));
+            }
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(p, keep)| keep.then_some(*p))
         .collect()
 }
+
+/// Distance from `p` to the segment `a`-`b` in the plane.
+fn segment_distance(a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length2 = dx * dx + dy * dy;
+    // Closed ways start and end on the same point
+    let t = if length2 == 0.0 {
+        0.0
+    } else {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length2).clamp(0.0, 1.0)
+    };
+    (p.0 - a.0 - dx * t).hypot(p.1 - a.1 - dy * t)
+}
+
+/// Map data as GeoJSON, e.g. to check coordinates and tags on geojson.io.
+/// POIs become points, closed ways polygons and other ways line strings, all with their tags
+/// as string properties. Polygon rings are counter-clockwise, as RFC 7946 asks.
+#[cfg(feature = "geojson")]
+pub fn to_geojson(data: &MapData) -> geojson::FeatureCollection {
+    use geojson::{Feature, FeatureCollection, Geometry, JsonObject, JsonValue, Value};
+
+    let position = |p: &(i32, i32)| {
+        let c = GeoCoord::from_stored(*p);
+        vec![c.lon, c.lat]
+    };
+    let feature = |value, tags: &Tags| Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(value)),
+        id: None,
+        properties: Some(
+            tags.iter()
+                .map(|(k, v)| (k.to_string(), JsonValue::from(v.as_str())))
+                .collect::<JsonObject>(),
+        ),
+        foreign_members: None,
+    };
+
+    let pois = data
+        .pois
+        .iter()
+        .map(|poi| feature(Value::Point(position(&poi.point)), &poi.tags));
+    let paths = data.paths.iter().map(|path| {
+        let value = if path.is_closed() {
+            let mut ring = path.points.clone();
+            ensure_winding(&mut ring, false);
+            Value::Polygon(vec![ring.iter().map(position).collect()])
+        } else {
+            Value::LineString(path.points.iter().map(position).collect())
+        };
+        feature(value, &path.tags)
+    });
+    FeatureCollection {
+        bbox: None,
+        features: pois.chain(paths).collect(),
+        foreign_members: None,
+    }
+}
+
+/// Map data from GeoJSON, e.g. to draw a non-OSM dataset or a hand-made test case.
+/// Points become POIs, line strings paths and polygons closed paths, multi-geometries one of
+/// each per part. Polygon holes are dropped, paths have no way to represent them.
+/// Positions off the globe are skipped, as are geometry collections.
+/// Properties become tags, with anything but strings written out as JSON.
+#[cfg(feature = "geojson")]
+pub fn from_geojson(fc: &geojson::FeatureCollection, options: &LoadOptions) -> MapData {
+    use geojson::Value;
+
+    let point = |p: &Vec<f64>| match p[..] {
+        [lon, lat, ..] => GeoCoord::try_from_latlon(lat, lon)
+            .ok()
+            .map(|c| c.to_stored()),
+        _ => None,
+    };
+    let line = |l: &Vec<Vec<f64>>| l.iter().filter_map(point).collect::<Vec<_>>();
+
+    let mut data = MapData {
+        paths: vec![],
+        pois: vec![],
+    };
+    for feature in &fc.features {
+        let Some(geometry) = &feature.geometry else {
+            continue;
+        };
+        let tags: Tags = feature
+            .properties
+            .iter()
+            .flatten()
+            .map(|(k, v)| {
+                let v = v.as_str().map_or_else(|| v.to_string(), str::to_owned);
+                (k.as_str().into(), v.into())
+            })
+            .collect();
+        let (points, lines) = match &geometry.value {
+            Value::Point(p) => (vec![p], vec![]),
+            Value::MultiPoint(ps) => (ps.iter().collect(), vec![]),
+            Value::LineString(l) => (vec![], vec![l]),
+            Value::MultiLineString(ls) => (vec![], ls.iter().collect()),
+            Value::Polygon(rings) => (vec![], rings.first().into_iter().collect()),
+            Value::MultiPolygon(polygons) => {
+                (vec![], polygons.iter().filter_map(|p| p.first()).collect())
+            }
+            Value::GeometryCollection(_) => continue,
+        };
+        data.pois
+            .extend(points.into_iter().filter_map(point).map(|point| Poi {
+                point,
+                tags: tags.clone(),
+            }));
+        // Imports are explicit, only an explicit filter drops anything
+        if !options.keep_way.as_ref().map_or(true, |keep| keep(&tags)) {
+            continue;
+        }
+        data.paths.extend(
+            lines
+                .into_iter()
+                .map(|l| build_path(line(l), tags.clone(), options))
+                .filter(|p| !p.points.is_empty()),
+        );
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::PaintStyle;
+    use egui::Color32;
+    use osmpbfreader::{fileformat, osmformat};
+    use protobuf::Message;
+
+    type TestNode<'a> = (i64, f64, f64, &'a [(&'a str, &'a str)]);
+    type TestWay<'a> = (i64, &'a [i64], &'a [(&'a str, &'a str)]);
+
+    /// A PBF extract with a single block, nodes as `(id, lat, lon, tags)`
+    /// and ways as `(id, node ids, tags)`.
+    fn extract(nodes: &[TestNode<'_>], ways: &[TestWay<'_>]) -> std::io::Cursor<Vec<u8>> {
+        let mut block = osmformat::PrimitiveBlock::new();
+        let mut strings = vec![String::new()];
+        let mut intern = |s: &str| match strings.iter().position(|t| t == s) {
+            Some(i) => i as u32,
+            None => {
+                strings.push(s.to_owned());
+                strings.len() as u32 - 1
+            }
+        };
+        let mut group = osmformat::PrimitiveGroup::new();
+        for &(id, lat, lon, tags) in nodes {
+            let mut node = osmformat::Node::new();
+            node.set_id(id);
+            // At the default granularity coordinates are in decimicro degrees
+            node.set_lat((lat * 1e7).round() as i64);
+            node.set_lon((lon * 1e7).round() as i64);
+            node.set_keys(tags.iter().map(|(k, _)| intern(k)).collect());
+            node.set_vals(tags.iter().map(|(_, v)| intern(v)).collect());
+            group.mut_nodes().push(node);
+        }
+        for &(id, refs, tags) in ways {
+            let mut way = osmformat::Way::new();
+            way.set_id(id);
+            way.set_keys(tags.iter().map(|(k, _)| intern(k)).collect());
+            way.set_vals(tags.iter().map(|(_, v)| intern(v)).collect());
+            // Delta coded
+            let mut previous = 0;
+            way.set_refs(
+                refs.iter()
+                    .map(|r| {
+                        let delta = r - previous;
+                        previous = *r;
+                        delta
+                    })
+                    .collect(),
+            );
+            group.mut_ways().push(way);
+        }
+        block.mut_primitivegroup().push(group);
+        for s in strings {
+            block.mut_stringtable().mut_s().push(s.into_bytes());
+        }
+
+        let raw = block.write_to_bytes().unwrap();
+        let mut blob = fileformat::Blob::new();
+        blob.set_raw_size(raw.len() as i32);
+        blob.set_raw(raw);
+        let blob = blob.write_to_bytes().unwrap();
+        let mut header = fileformat::BlobHeader::new();
+        header.set_field_type("OSMData".into());
+        header.set_datasize(blob.len() as i32);
+        let header = header.write_to_bytes().unwrap();
+
+        let mut bytes = (header.len() as u32).to_be_bytes().to_vec();
+        bytes.extend(header);
+        bytes.extend(blob);
+        std::io::Cursor::new(bytes)
+    }
+
+    const TRACK: &[TestNode<'_>] = &[
+        (1, 60.170, 24.940, &[]),
+        (2, 60.171, 24.941, &[]),
+        (3, 60.172, 24.943, &[]),
+    ];
+
+    #[test]
+    fn custom_rules_bring_their_ways_along() {
+        let ways: &[TestWay<'_>] = &[(10, &[1, 2, 3], &[("railway", "rail")])];
+        let default = read_nodes(extract(TRACK, ways), &LoadOptions::default()).unwrap();
+        assert!(default.paths.is_empty());
+
+        let rail = PaintStyle::new(Color32::BLACK, 2.0).dashed(8.0);
+        let options = LoadOptions {
+            styles: StyleSheet::default().with("railway", None, rail.clone()),
+            ..Default::default()
+        };
+        let data = read_nodes(extract(TRACK, ways), &options).unwrap();
+        assert_eq!(data.paths.len(), 1);
+        assert_eq!(data.paths[0].points.len(), 3);
+        assert_eq!(options.styles.resolve(&data.paths[0].tags), &rail);
+    }
+
+    #[test]
+    fn way_filter_decides_what_is_loaded() {
+        let nodes: &[TestNode<'_>] = &[
+            (1, 60.170, 24.940, &[]),
+            (2, 60.171, 24.941, &[]),
+            (3, 60.172, 24.943, &[]),
+            (4, 60.180, 24.950, &[]),
+            (5, 60.181, 24.951, &[]),
+        ];
+        let ways: &[TestWay<'_>] = &[
+            (10, &[1, 2], &[("highway", "residential")]),
+            (11, &[2, 3], &[("railway", "rail")]),
+            (12, &[4, 5, 4], &[("building", "yes")]),
+        ];
+        let loaded = |options: &LoadOptions| {
+            let data = read_nodes(extract(nodes, ways), options).unwrap();
+            let mut keys: Vec<_> = data
+                .paths
+                .iter()
+                .flat_map(|p| p.tags.keys().map(|k| k.to_string()))
+                .collect();
+            keys.sort();
+            let points: Vec<_> = data.paths.iter().flat_map(|p| p.points.clone()).collect();
+            (keys, points)
+        };
+        assert_eq!(loaded(&LoadOptions::default()).0, ["highway"]);
+
+        // Built at runtime, like toggles from the side panel, and free to widen the styled set
+        let wanted = String::from("railway");
+        let options = LoadOptions {
+            keep_way: Some(Arc::new(move |tags: &Tags| {
+                tags.contains_key(wanted.as_str())
+            })),
+            ..Default::default()
+        };
+        let (keys, points) = loaded(&options);
+        assert_eq!(keys, ["railway"]);
+        // Only the railway's own nodes made it in
+        let stored = |id: usize| GeoCoord::from_latlon(nodes[id].1, nodes[id].2).to_stored();
+        assert_eq!(points, [stored(1), stored(2)]);
+    }
+
+    #[test]
+    fn straight_line_collapses_to_its_ends() {
+        assert_eq!(
+            remove_collinear(&[(0, 0), (5, 5), (10, 10)]),
+            vec![(0, 0), (10, 10)]
+        );
+    }
+
+    #[test]
+    fn doubling_back_keeps_the_turning_point() {
+        let points = [(0, 0), (10, 0), (5, 0)];
+        assert_eq!(remove_collinear(&points), points.to_vec());
+    }
+
+    #[test]
+    fn closed_ring_stays_closed() {
+        let ring = [(0, 0), (5, 0), (10, 0), (10, 10), (0, 10), (0, 0)];
+        assert_eq!(
+            remove_collinear(&ring),
+            vec![(0, 0), (10, 0), (10, 10), (0, 10), (0, 0)]
+        );
+    }
+
+    /// A unit square in lat/lon, counter-clockwise as seen on a map with north up.
+    fn ccw_square() -> Vec<(i32, i32)> {
+        [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)]
+            .iter()
+            .map(|&(lat, lon)| GeoCoord::from_latlon(lat, lon).to_stored())
+            .collect()
+    }
+
+    #[test]
+    fn counter_clockwise_ring_has_positive_area_after_negation() {
+        assert!(signed_area2(&ccw_square()) > 0);
+    }
+
+    #[test]
+    fn winding_is_made_clockwise_and_back() {
+        let mut ring = ccw_square();
+        ensure_winding(&mut ring, true);
+        assert!(signed_area2(&ring) < 0);
+        assert_eq!(ring.first(), ring.last());
+        ensure_winding(&mut ring, false);
+        assert_eq!(ring, ccw_square());
+    }
+
+    #[test]
+    fn tiny_ring_still_gets_its_winding() {
+        // Twice the area is 1, the smallest a ring on integer coordinates can have
+        let mut ring = vec![(0, 0), (1, 0), (1, 1), (0, 0)];
+        assert_eq!(signed_area2(&ring), 1);
+        ensure_winding(&mut ring, true);
+        assert_eq!(ring, vec![(0, 0), (1, 1), (1, 0), (0, 0)]);
+        assert_eq!(signed_area2(&ring), -1);
+    }
+
+    #[test]
+    fn ring_without_area_is_left_alone() {
+        let mut ring = vec![(0, 0), (10, 0), (20, 0), (0, 0)];
+        ensure_winding(&mut ring, true);
+        assert_eq!(ring, vec![(0, 0), (10, 0), (20, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn garbage_is_an_error() {
+        // A fixed xorshift stream, so a failure can be reproduced
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let bytes: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let result = read_nodes(std::io::Cursor::new(bytes), &LoadOptions::default());
+        assert!(result.is_err());
+    }
+
+    fn stored(lat: f64, lon: f64) -> (i32, i32) {
+        GeoCoord::from_latlon(lat, lon).to_stored()
+    }
+
+    /// Wiggles of about a meter either side of a straight line east
+    fn zig_zag() -> Vec<(i32, i32)> {
+        (0..20)
+            .map(|i| stored(60.0 + (i % 2) as f64 * 0.000_02, 24.0 + i as f64 * 0.001))
+            .collect()
+    }
+
+    #[test]
+    fn zig_zag_within_tolerance_collapses() {
+        let points = zig_zag();
+        let simplified = simplify_path(&points, 5.0);
+        assert_eq!(simplified, [points[0], points[19]]);
+        // Wiggles bigger than the tolerance stay
+        assert_eq!(simplify_path(&points, 1.0), points);
+    }
+
+    #[test]
+    fn simplification_keeps_the_ends() {
+        let points = zig_zag();
+        for tolerance in [0.5, 5.0, 1e6] {
+            let simplified = simplify_path(&points, tolerance);
+            assert_eq!(simplified.first(), points.first());
+            assert_eq!(simplified.last(), points.last());
+        }
+    }
+
+    #[test]
+    fn simplified_rings_stay_closed() {
+        let mut ring: Vec<_> = (0..36)
+            .map(|i| {
+                let angle = (i as f64 * 10.0).to_radians();
+                stored(60.0 + 0.001 * angle.sin(), 24.0 + 0.002 * angle.cos())
+            })
+            .collect();
+        ring.push(ring[0]);
+        for tolerance in [5.0, 20.0, 1e6] {
+            let simplified = simplify_path(&ring, tolerance);
+            assert_eq!(simplified.first(), simplified.last());
+            assert!(simplified.len() < ring.len());
+        }
+    }
+
+    #[test]
+    fn zero_tolerance_keeps_everything() {
+        let line = [stored(60.0, 24.0), stored(60.0, 24.1), stored(60.0, 24.2)];
+        assert_eq!(simplify_path(&line, 0.0), line);
+    }
+
+    /// Twice the area the triangles cover, to hold against [`signed_area2`]
+    fn covered_area2(ring: &[(i32, i32)], triangles: &[[usize; 3]]) -> i64 {
+        triangles
+            .iter()
+            .map(|t| signed_area2(&[ring[t[0]], ring[t[1]], ring[t[2]]]).abs())
+            .sum()
+    }
+
+    #[test]
+    fn square_is_two_triangles() {
+        let square = ccw_square();
+        let triangles = triangulate(&square);
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(
+            covered_area2(&square, &triangles),
+            signed_area2(&square).abs()
+        );
+    }
+
+    #[test]
+    fn rings_without_three_distinct_points_are_skipped() {
+        let a = stored(60.0, 24.0);
+        let b = stored(60.0, 24.1);
+        assert!(triangulate(&[]).is_empty());
+        assert!(triangulate(&[a, b, a]).is_empty());
+        assert!(triangulate(&[a, b, b, a]).is_empty());
+        assert!(triangulate(&[a, a, a, a]).is_empty());
+    }
+
+    #[test]
+    fn self_intersecting_ring_does_not_panic() {
+        // A bowtie, whose two halves cancel out to no area
+        let bowtie = [
+            stored(0.0, 0.0),
+            stored(1.0, 1.0),
+            stored(1.0, 0.0),
+            stored(0.0, 1.0),
+            stored(0.0, 0.0),
+        ];
+        let triangles = triangulate(&bowtie);
+        assert!(triangles.iter().flatten().all(|&i| i < bowtie.len()));
+    }
+
+    #[test]
+    fn duplicate_vertices_still_triangulate() {
+        let mut square = ccw_square();
+        square.insert(2, square[1]);
+        let triangles = triangulate(&square);
+        assert!(!triangles.is_empty());
+        assert!(triangles.iter().flatten().all(|&i| i < square.len()));
+        assert_eq!(
+            covered_area2(&square, &triangles),
+            signed_area2(&square).abs()
+        );
+    }
+
+    fn path(points: &[(f64, f64)]) -> Path {
+        let points: Vec<_> = points.iter().map(|&(lat, lon)| stored(lat, lon)).collect();
+        build_path(points, Tags::new(), &LoadOptions::default())
+    }
+
+    #[test]
+    fn single_point_has_a_zero_circle() {
+        let (center, radius) = bounding_circle(&[path(&[(60.17, 24.94)])]).unwrap();
+        assert_eq!(center.to_stored(), stored(60.17, 24.94));
+        assert_eq!(radius, 0.0);
+        assert!(bounding_circle(&[]).is_none());
+    }
+
+    #[test]
+    fn circle_across_the_antimeridian_stays_small() {
+        let paths = [path(&[(0.0, 179.9), (0.0, 180.0)]), path(&[(0.0, -179.9)])];
+        let (center, radius) = bounding_circle(&paths).unwrap();
+        assert!(center.lon.abs() > 179.99, "{center:?}");
+        // 0.1° along the equator, not half the globe
+        assert!((radius - 11_120.0).abs() < 10.0, "{radius}");
+    }
+
+    fn poi(lat: f64, lon: f64, name: &str) -> Poi {
+        Poi {
+            point: stored(lat, lon),
+            tags: [("name".into(), name.into())].into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn nearest_poi_stops_at_the_radius() {
+        let pois = [poi(60.0, 24.0, "near"), poi(60.01, 24.0, "far")];
+        let target = GeoCoord::from_latlon(60.0009, 24.0);
+        let (nearest, distance) = nearest_poi(&pois, &target, 200.0).unwrap();
+        assert_eq!(nearest.tags.get("name").unwrap(), "near");
+        assert!((distance - 100.0).abs() < 1.0, "{distance}");
+        assert!(nearest_poi(&pois, &target, 90.0).is_none());
+        assert!(nearest_poi(&[], &target, 1e9).is_none());
+    }
+
+    #[test]
+    fn nearest_poi_ties_go_to_the_first() {
+        // Two shops in the same building
+        let pois = [poi(60.001, 24.0, "first"), poi(60.001, 24.0, "second")];
+        let target = GeoCoord::from_latlon(60.0, 24.0);
+        let (nearest, _) = nearest_poi(&pois, &target, 1e3).unwrap();
+        assert_eq!(nearest.tags.get("name").unwrap(), "first");
+    }
+
+    #[test]
+    fn nearest_path_measures_to_the_segment_not_its_ends() {
+        // A kilometre-long street running east, and a lone point 200 m away
+        let street = path(&[(60.0, 24.0), (60.0, 24.018)]);
+        let point = path(&[(60.0027, 24.009)]);
+        // 100 m above the middle of the street, 500 m from either of its ends
+        let target = GeoCoord::from_latlon(60.0009, 24.009);
+        let paths = [point, street];
+        let (nearest, distance) = nearest_path(&paths, &target).unwrap();
+        assert_eq!(nearest.points, paths[1].points);
+        assert!((distance - 100.0).abs() < 1.0, "{distance}");
+    }
+
+    #[test]
+    fn nearest_path_ties_go_to_the_first() {
+        let north = path(&[(60.001, 24.0), (60.001, 24.01)]);
+        let south = path(&[(59.999, 24.0), (59.999, 24.01)]);
+        let target = GeoCoord::from_latlon(60.0, 24.005);
+        let paths = [north, south];
+        let (nearest, _) = nearest_path(&paths, &target).unwrap();
+        assert_eq!(nearest.points, paths[0].points);
+        let (nearest, _) = nearest_path(paths.iter().rev(), &target).unwrap();
+        assert_eq!(nearest.points, paths[1].points);
+    }
+}
+
+#[cfg(all(test, feature = "geojson"))]
+mod geojson_tests {
+    use super::*;
+    use geojson::{FeatureCollection, Value};
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).into(), (*v).into()))
+            .collect()
+    }
+
+    fn stored(lat: f64, lon: f64) -> (i32, i32) {
+        GeoCoord::from_latlon(lat, lon).to_stored()
+    }
+
+    #[test]
+    fn export_and_import_round_trip() {
+        let options = LoadOptions::default();
+        let road = [stored(60.17, 24.94), stored(60.1712345, 24.9512345)];
+        let lake = [
+            stored(60.20, 24.90),
+            stored(60.21, 24.90),
+            stored(60.21, 24.92),
+            stored(60.20, 24.90),
+        ];
+        let data = MapData {
+            paths: vec![
+                build_path(road.to_vec(), tags(&[("highway", "primary")]), &options),
+                build_path(lake.to_vec(), tags(&[("natural", "water")]), &options),
+            ],
+            pois: vec![Poi {
+                point: stored(60.1698765, 24.9383456),
+                tags: tags(&[("amenity", "cafe"), ("name", "Kahvila")]),
+            }],
+        };
+
+        let fc = to_geojson(&data);
+        let kinds: Vec<_> = fc
+            .features
+            .iter()
+            .map(|f| match f.geometry.as_ref().map(|g| &g.value) {
+                Some(Value::Point(_)) => "point",
+                Some(Value::LineString(_)) => "line",
+                Some(Value::Polygon(_)) => "polygon",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(kinds, ["point", "line", "polygon"]);
+
+        let back = from_geojson(&fc, &options);
+        assert_eq!(back.pois.len(), 1);
+        assert_eq!(back.pois[0].point, data.pois[0].point);
+        assert_eq!(back.pois[0].tags, data.pois[0].tags);
+        assert_eq!(back.paths.len(), 2);
+        for (back, original) in back.paths.iter().zip(&data.paths) {
+            assert_eq!(back.points, original.points);
+            assert_eq!(back.tags, original.tags);
+            assert_eq!(back.is_closed(), original.is_closed());
+        }
+    }
+
+    #[test]
+    fn off_globe_positions_and_collections_are_skipped() {
+        let fc: FeatureCollection = r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {"type": "Feature", "properties": {"amenity": "cafe"},
+                 "geometry": {"type": "Point", "coordinates": [200.0, 60.0]}},
+                {"type": "Feature", "properties": {"highway": "primary"},
+                 "geometry": {"type": "LineString",
+                              "coordinates": [[24.9, 60.1], [24.95, 91.0], [25.0, 60.2]]}},
+                {"type": "Feature", "properties": {"amenity": "bench"},
+                 "geometry": {"type": "GeometryCollection", "geometries": [
+                     {"type": "Point", "coordinates": [24.9, 60.1]}
+                 ]}}
+            ]
+        }"#
+        .parse()
+        .unwrap();
+        let data = from_geojson(&fc, &LoadOptions::default());
+        assert!(data.pois.is_empty());
+        assert_eq!(data.paths.len(), 1);
+        assert_eq!(
+            data.paths[0].points,
+            [stored(60.1, 24.9), stored(60.2, 25.0)]
+        );
+    }
+}